@@ -0,0 +1,101 @@
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::FromPrimitive;
+
+use rust_binance_pricing::data_manip::{NativeBook, Orderbook};
+
+/// Best-first bids and asks around 96000 with `n` levels a side
+fn levels(n: usize) -> (Vec<[Decimal; 2]>, Vec<[Decimal; 2]>) {
+    let level = |price: f64, i: usize| {
+        [
+            Decimal::from_f64(price).unwrap().round_dp(1),
+            Decimal::from_f64(0.5 + i as f64 * 0.013)
+                .unwrap()
+                .round_dp(3),
+        ]
+    };
+
+    let bids = (1..=n)
+        .map(|i| level(96_000.0 - i as f64 * 0.1, i))
+        .collect();
+    let asks = (1..=n)
+        .map(|i| level(96_000.1 + i as f64 * 0.1, i))
+        .collect();
+    (bids, asks)
+}
+
+/// Both paths must agree before their speed is worth comparing
+fn check_same_profile(bids: &[[Decimal; 2]], asks: &[[Decimal; 2]]) {
+    let native = NativeBook::from_levels(bids.iter().copied(), asks.iter().copied());
+    let df = Orderbook::from_levels(bids, asks)
+        .unwrap()
+        .calculate_depth()
+        .unwrap();
+
+    let column = |name: &str| -> Vec<f64> {
+        let c = df
+            .column(name)
+            .unwrap()
+            .cast(&polars::prelude::DataType::Float64);
+        c.unwrap().f64().unwrap().into_no_null_iter().collect()
+    };
+    let prices = column("price");
+    let level_ids = column("level_id");
+    let cumulative = column("cumulative_depth");
+    let bps = column("bps_from_mid");
+
+    assert_eq!(native.rows().len(), df.height());
+    for (i, row) in native.rows().iter().enumerate() {
+        assert_eq!(row.price, prices[i], "price at row {i}");
+        assert_eq!(row.level_id as f64, level_ids[i], "level at row {i}");
+        assert!((row.bps_from_mid - bps[i]).abs() < 1e-9, "bps at row {i}");
+        assert!(
+            (row.cumulative_depth - cumulative[i]).abs() < 1e-9,
+            "cumulative depth at row {i}"
+        );
+    }
+}
+
+fn bench_depth(c: &mut Criterion) {
+    let mut group = c.benchmark_group("book_depth");
+
+    // depth20 partial stream and a --full-book snapshot
+    for n in [20, 500] {
+        let (bids, asks) = levels(n);
+        check_same_profile(&bids, &asks);
+
+        group.bench_with_input(BenchmarkId::new("polars", n), &n, |b, _| {
+            b.iter(|| {
+                let mut ob = Orderbook::from_levels(black_box(&bids), black_box(&asks)).unwrap();
+                black_box(ob.calculate_depth().unwrap());
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("native", n), &n, |b, _| {
+            b.iter(|| {
+                let book = NativeBook::from_levels(
+                    black_box(&bids).iter().copied(),
+                    black_box(&asks).iter().copied(),
+                );
+                black_box(book.rows().len());
+            })
+        });
+
+        // the row buffer kept across updates
+        let mut book = NativeBook::default();
+        group.bench_with_input(BenchmarkId::new("native_reused", n), &n, |b, _| {
+            b.iter(|| {
+                book.rebuild(
+                    black_box(&bids).iter().copied(),
+                    black_box(&asks).iter().copied(),
+                );
+                black_box(book.rows().len());
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_depth);
+criterion_main!(benches);
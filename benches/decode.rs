@@ -0,0 +1,95 @@
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use serde::Deserialize;
+use serde_json::Value;
+
+use rust_binance_pricing::market::Market;
+use rust_binance_pricing::types::{
+    AggTradeData, BookTickerData, DepthUpdateData, MarkPriceUpdateData, decode_message,
+};
+
+/// The envelope the handler used before typed dispatch
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Legacy<T> {
+    stream: String,
+    data: T,
+}
+
+fn depth_levels(mid: f64, side: f64) -> String {
+    (1..=20)
+        .map(|i| {
+            format!(
+                "[\"{:.1}\",\"{:.3}\"]",
+                mid + side * i as f64 * 0.1,
+                0.5 + i as f64
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn samples() -> Vec<(&'static str, String)> {
+    let depth = format!(
+        r#"{{"stream":"btcusdt@depth20@100ms","data":{{"e":"depthUpdate","E":1733011200123,"T":1733011200120,"s":"BTCUSDT","U":7010152360351,"u":7010152365502,"pu":7010152360320,"b":[{}],"a":[{}]}}}}"#,
+        depth_levels(96_000.0, -1.0),
+        depth_levels(96_000.1, 1.0)
+    );
+
+    vec![
+        ("depthUpdate", depth),
+        (
+            "aggTrade",
+            r#"{"stream":"btcusdt@aggTrade","data":{"e":"aggTrade","E":1733011200123,"a":2455912384,"s":"BTCUSDT","p":"96000.10","q":"0.015","f":5814938211,"l":5814938214,"T":1733011200120,"m":true}}"#.to_string(),
+        ),
+        (
+            "bookTicker",
+            r#"{"stream":"btcusdt@bookTicker","data":{"e":"bookTicker","u":7010152365502,"s":"BTCUSDT","b":"96000.00","B":"3.412","a":"96000.10","A":"1.027","T":1733011200120,"E":1733011200123}}"#.to_string(),
+        ),
+        (
+            "markPriceUpdate",
+            r#"{"stream":"btcusdt@markPrice@1s","data":{"e":"markPriceUpdate","E":1733011200000,"s":"BTCUSDT","p":"96001.23000000","P":"95998.81234567","i":"95997.12000000","r":"0.00010000","T":1733040000000}}"#.to_string(),
+        ),
+    ]
+}
+
+/// Previous path: parse to `Value` to read `data.e`, then parse again into the concrete type
+fn legacy_decode(raw: &str) -> anyhow::Result<()> {
+    let ws_msg: Value = serde_json::from_str(raw)?;
+    match ws_msg["data"]["e"].as_str().unwrap_or("") {
+        "depthUpdate" => {
+            black_box(serde_json::from_str::<Legacy<DepthUpdateData>>(raw)?);
+        }
+        "aggTrade" => {
+            black_box(serde_json::from_str::<Legacy<AggTradeData>>(raw)?);
+        }
+        "bookTicker" => {
+            black_box(serde_json::from_str::<Legacy<BookTickerData>>(raw)?);
+        }
+        "markPriceUpdate" => {
+            black_box(serde_json::from_str::<Legacy<MarkPriceUpdateData>>(raw)?);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+
+    for (name, raw) in samples() {
+        group.bench_with_input(
+            BenchmarkId::new("value_then_typed", name),
+            &raw,
+            |b, raw| b.iter(|| legacy_decode(black_box(raw)).unwrap()),
+        );
+
+        group.bench_with_input(BenchmarkId::new("single_pass", name), &raw, |b, raw| {
+            b.iter(|| black_box(decode_message(Market::UsdM, black_box(raw)).unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
@@ -0,0 +1,174 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use rust_binance_pricing::market::Market;
+use rust_binance_pricing::types::{DepthUpdateData, decode_message};
+
+/// Counts every allocation so each decode path can report allocations per update
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// The previous level parser: two heap strings per level before parsing
+fn legacy_levels<'de, D>(deserializer: D) -> Result<Vec<[Decimal; 2]>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let v: Vec<[String; 2]> = Deserialize::deserialize(deserializer)?;
+    v.into_iter()
+        .map(|[s1, s2]| {
+            let d1 = Decimal::from_str(&s1).map_err(serde::de::Error::custom)?;
+            let d2 = Decimal::from_str(&s2).map_err(serde::de::Error::custom)?;
+            Ok([d1, d2])
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct LegacyDepth {
+    #[serde(rename = "E")]
+    e2: i64,
+    #[serde(rename = "T")]
+    t: i64,
+    s: String,
+    #[serde(rename = "U")]
+    u: i64,
+    #[serde(rename = "u")]
+    u2: i64,
+    #[serde(rename = "pu")]
+    p: i64,
+    #[serde(deserialize_with = "legacy_levels")]
+    b: Vec<[Decimal; 2]>,
+    #[serde(deserialize_with = "legacy_levels")]
+    a: Vec<[Decimal; 2]>,
+}
+
+/// The previous live path: an internally tagged enum, which makes serde buffer the whole
+/// payload before picking the variant
+#[derive(Deserialize)]
+#[serde(tag = "e")]
+#[allow(dead_code)]
+enum Tagged {
+    #[serde(rename = "depthUpdate")]
+    DepthUpdate(DepthUpdateData),
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Envelope<T> {
+    stream: String,
+    data: T,
+}
+
+fn depth_levels(mid: f64, side: f64) -> String {
+    (1..=20)
+        .map(|i| {
+            format!(
+                "[\"{:.1}\",\"{:.3}\"]",
+                mid + side * i as f64 * 0.1,
+                0.5 + i as f64
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn payload() -> String {
+    format!(
+        r#"{{"e":"depthUpdate","E":1733011200123,"T":1733011200120,"s":"BTCUSDT","U":7010152360351,"u":7010152365502,"pu":7010152360320,"b":[{}],"a":[{}]}}"#,
+        depth_levels(96_000.0, -1.0),
+        depth_levels(96_000.1, 1.0)
+    )
+}
+
+/// The frame as it arrives on the combined stream
+fn frame() -> String {
+    format!(
+        r#"{{"stream":"btcusdt@depth20@100ms","data":{}}}"#,
+        payload()
+    )
+}
+
+fn allocations_per_call(runs: usize, mut f: impl FnMut()) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..runs {
+        f();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / runs as f64
+}
+
+fn bench_depth(c: &mut Criterion) {
+    let raw = frame();
+    let bare = payload();
+    let mut reused = DepthUpdateData::default();
+    reused.decode_into(&bare).unwrap();
+
+    let legacy = allocations_per_call(1_000, || {
+        black_box(serde_json::from_str::<Envelope<LegacyDepth>>(&raw).unwrap());
+    });
+    let tagged = allocations_per_call(1_000, || {
+        black_box(serde_json::from_str::<Envelope<Tagged>>(&raw).unwrap());
+    });
+    let live = allocations_per_call(1_000, || {
+        black_box(decode_message(Market::UsdM, &raw).unwrap());
+    });
+    let reusing = allocations_per_call(1_000, || {
+        reused.decode_into(black_box(&bare)).unwrap();
+    });
+
+    println!("allocations per depth20 frame (20x20 levels)");
+    println!("  owned strings:        {legacy:>6.1}");
+    println!("  tagged enum:          {tagged:>6.1}");
+    println!("  decode_message:       {live:>6.1}");
+    println!("  reused buffers:       {reusing:>6.1} (bare payload)");
+
+    let mut group = c.benchmark_group("depth_update");
+
+    group.bench_function("owned_strings", |b| {
+        b.iter(|| {
+            black_box(serde_json::from_str::<Envelope<LegacyDepth>>(black_box(&raw)).unwrap())
+        })
+    });
+
+    group.bench_function("tagged_enum", |b| {
+        b.iter(|| black_box(serde_json::from_str::<Envelope<Tagged>>(black_box(&raw)).unwrap()))
+    });
+
+    group.bench_function("decode_message", |b| {
+        b.iter(|| black_box(decode_message(Market::UsdM, black_box(&raw)).unwrap()))
+    });
+
+    group.bench_function("reused_buffers", |b| {
+        b.iter(|| reused.decode_into(black_box(&bare)).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_depth);
+criterion_main!(benches);
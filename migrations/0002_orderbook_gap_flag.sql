@@ -0,0 +1,2 @@
+ALTER TABLE orderbook_updates
+ADD COLUMN IF NOT EXISTS gap_before BOOLEAN NOT NULL DEFAULT FALSE;
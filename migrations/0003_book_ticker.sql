@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS
+    book_ticker (
+        event_time timestamptz NOT NULL,
+        transaction_time timestamptz,
+        symbol VARCHAR NOT NULL,
+        update_id BIGINT NOT NULL,
+        bid_price NUMERIC(30, 10) NOT NULL,
+        bid_qty NUMERIC(30, 10) NOT NULL,
+        ask_price NUMERIC(30, 10) NOT NULL,
+        ask_qty NUMERIC(30, 10) NOT NULL
+    );
+
+
+
+CREATE INDEX IF NOT EXISTS idx_book_ticker_symbol_ts ON book_ticker (symbol, event_time DESC);
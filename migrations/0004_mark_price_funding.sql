@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS
+    mark_price (
+        event_time timestamptz NOT NULL,
+        symbol VARCHAR NOT NULL,
+        mark_price NUMERIC(30, 10) NOT NULL,
+        index_price NUMERIC(30, 10) NOT NULL,
+        est_settle_price NUMERIC(30, 10) NOT NULL,
+        funding_rate NUMERIC(30, 10) NOT NULL,
+        next_funding_time timestamptz NOT NULL
+    );
+
+
+
+CREATE INDEX IF NOT EXISTS idx_mark_price_symbol_ts ON mark_price (symbol, event_time DESC);
+
+
+
+CREATE TABLE IF NOT EXISTS
+    funding_events (
+        symbol VARCHAR NOT NULL,
+        funding_time timestamptz NOT NULL,
+        funding_rate NUMERIC(30, 10) NOT NULL,
+        mark_price NUMERIC(30, 10) NOT NULL,
+        index_price NUMERIC(30, 10) NOT NULL,
+        PRIMARY KEY (symbol, funding_time)
+    );
@@ -0,0 +1,16 @@
+ALTER TABLE market_trade
+ADD COLUMN IF NOT EXISTS agg_trade_id BIGINT,
+ADD COLUMN IF NOT EXISTS first_trade_id BIGINT,
+ADD COLUMN IF NOT EXISTS last_trade_id BIGINT,
+ADD COLUMN IF NOT EXISTS event_time timestamptz;
+
+
+
+-- large sweeps can aggregate more than 32767 fills
+ALTER TABLE market_trade
+ALTER COLUMN num_trades TYPE INTEGER;
+
+
+
+-- rows captured before this migration have no id and are not constrained
+CREATE UNIQUE INDEX IF NOT EXISTS uq_market_trade_symbol_agg_id ON market_trade (symbol, agg_trade_id);
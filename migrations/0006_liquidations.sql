@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS
+    liquidations (
+        event_time timestamptz NOT NULL,
+        trade_time timestamptz NOT NULL,
+        symbol VARCHAR NOT NULL,
+        side VARCHAR NOT NULL,
+        order_type VARCHAR NOT NULL,
+        time_in_force VARCHAR NOT NULL,
+        orig_qty NUMERIC(30, 10) NOT NULL,
+        price NUMERIC(30, 10) NOT NULL,
+        avg_price NUMERIC(30, 10) NOT NULL,
+        status VARCHAR NOT NULL,
+        last_filled_qty NUMERIC(30, 10) NOT NULL,
+        accumulated_qty NUMERIC(30, 10) NOT NULL
+    );
+
+
+
+CREATE INDEX IF NOT EXISTS idx_liquidations_symbol_ts ON liquidations (symbol, trade_time DESC);
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS
+    klines (
+        symbol VARCHAR NOT NULL,
+        interval VARCHAR NOT NULL,
+        open_time timestamptz NOT NULL,
+        close_time timestamptz NOT NULL,
+        contract_type VARCHAR,
+        event_time timestamptz NOT NULL,
+        open NUMERIC(30, 10) NOT NULL,
+        high NUMERIC(30, 10) NOT NULL,
+        low NUMERIC(30, 10) NOT NULL,
+        close NUMERIC(30, 10) NOT NULL,
+        volume NUMERIC(30, 10) NOT NULL,
+        quote_volume NUMERIC(30, 10) NOT NULL,
+        num_trades BIGINT NOT NULL,
+        taker_buy_volume NUMERIC(30, 10) NOT NULL,
+        taker_buy_quote_volume NUMERIC(30, 10) NOT NULL,
+        first_id BIGINT NOT NULL,
+        last_id BIGINT NOT NULL,
+        is_closed BOOLEAN NOT NULL,
+        PRIMARY KEY (symbol, interval, open_time)
+    );
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS
+    bars (
+        symbol VARCHAR NOT NULL,
+        spec VARCHAR NOT NULL,
+        open_time timestamptz NOT NULL,
+        close_time timestamptz NOT NULL,
+        open NUMERIC(30, 10) NOT NULL,
+        high NUMERIC(30, 10) NOT NULL,
+        low NUMERIC(30, 10) NOT NULL,
+        close NUMERIC(30, 10) NOT NULL,
+        volume NUMERIC(30, 10) NOT NULL,
+        quote_volume NUMERIC(30, 10) NOT NULL,
+        buy_volume NUMERIC(30, 10) NOT NULL,
+        agg_trades INTEGER NOT NULL,
+        first_agg_id BIGINT NOT NULL,
+        last_agg_id BIGINT NOT NULL,
+        PRIMARY KEY (symbol, spec, first_agg_id)
+    );
+
+
+
+CREATE INDEX IF NOT EXISTS idx_bars_symbol_spec_ts ON bars (symbol, spec, open_time DESC);
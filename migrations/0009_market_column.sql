@@ -0,0 +1,81 @@
+-- everything captured so far came from the USD-M futures endpoint
+ALTER TABLE market_trade
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+ALTER TABLE orderbook_updates
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+ALTER TABLE book_ticker
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+ALTER TABLE mark_price
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+ALTER TABLE funding_events
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+ALTER TABLE liquidations
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+ALTER TABLE klines
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+ALTER TABLE bars
+ADD COLUMN IF NOT EXISTS market VARCHAR NOT NULL DEFAULT 'usdm';
+
+
+
+-- new rows must say where they came from
+ALTER TABLE market_trade
+ALTER COLUMN market DROP DEFAULT;
+
+ALTER TABLE orderbook_updates
+ALTER COLUMN market DROP DEFAULT;
+
+ALTER TABLE book_ticker
+ALTER COLUMN market DROP DEFAULT;
+
+ALTER TABLE mark_price
+ALTER COLUMN market DROP DEFAULT;
+
+ALTER TABLE funding_events
+ALTER COLUMN market DROP DEFAULT;
+
+ALTER TABLE liquidations
+ALTER COLUMN market DROP DEFAULT;
+
+ALTER TABLE klines
+ALTER COLUMN market DROP DEFAULT;
+
+ALTER TABLE bars
+ALTER COLUMN market DROP DEFAULT;
+
+
+
+-- the same symbol trades on several markets, so every natural key includes the market
+DROP INDEX IF EXISTS uq_market_trade_symbol_agg_id;
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_market_trade_market_symbol_agg_id ON market_trade (market, symbol, agg_trade_id);
+
+ALTER TABLE funding_events
+DROP CONSTRAINT IF EXISTS funding_events_pkey,
+ADD PRIMARY KEY (market, symbol, funding_time);
+
+ALTER TABLE klines
+DROP CONSTRAINT IF EXISTS klines_pkey,
+ADD PRIMARY KEY (market, symbol, interval, open_time);
+
+ALTER TABLE bars
+DROP CONSTRAINT IF EXISTS bars_pkey,
+ADD PRIMARY KEY (market, symbol, spec, first_agg_id);
+
+
+
+-- COIN-M publishes no index price on the mark price stream and no funding rate for
+-- delivery contracts
+ALTER TABLE mark_price
+ALTER COLUMN index_price DROP NOT NULL,
+ALTER COLUMN funding_rate DROP NOT NULL;
+
+ALTER TABLE funding_events
+ALTER COLUMN index_price DROP NOT NULL;
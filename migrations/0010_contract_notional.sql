@@ -0,0 +1,17 @@
+-- COIN-M quantities are contracts; contract_size is NULL on linear markets where
+-- quantity is already in the base asset. quote_notional is USD on COIN-M.
+ALTER TABLE market_trade
+ADD COLUMN IF NOT EXISTS contract_size NUMERIC(30, 10),
+ADD COLUMN IF NOT EXISTS base_qty NUMERIC(30, 10),
+ADD COLUMN IF NOT EXISTS quote_notional NUMERIC(30, 10);
+
+
+
+ALTER TABLE orderbook_updates
+ADD COLUMN IF NOT EXISTS contract_size NUMERIC(30, 10);
+
+
+
+ALTER TABLE orderbook_levels
+ADD COLUMN IF NOT EXISTS base_qty NUMERIC(30, 10),
+ADD COLUMN IF NOT EXISTS quote_notional NUMERIC(30, 10);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS
+    basis (
+        ts timestamptz NOT NULL,
+        symbol VARCHAR NOT NULL,
+        spot_mid DOUBLE PRECISION NOT NULL,
+        perp_mid DOUBLE PRECISION NOT NULL,
+        mark_price DOUBLE PRECISION,
+        basis DOUBLE PRECISION NOT NULL,
+        basis_bps DOUBLE PRECISION NOT NULL,
+        mark_basis_bps DOUBLE PRECISION,
+        annualised_basis DOUBLE PRECISION NOT NULL,
+        PRIMARY KEY (symbol, ts)
+    );
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS
+    orderbook_metrics (
+        market VARCHAR NOT NULL,
+        symbol VARCHAR NOT NULL,
+        event_time timestamptz NOT NULL,
+        last_update_id BIGINT NOT NULL,
+        mid DOUBLE PRECISION NOT NULL,
+        spread_bps DOUBLE PRECISION NOT NULL,
+        microprice DOUBLE PRECISION NOT NULL,
+        weighted_mid DOUBLE PRECISION NOT NULL,
+        -- imbalance[i] is over the top imbalance_levels[i] levels
+        imbalance_levels INTEGER[] NOT NULL,
+        imbalance DOUBLE PRECISION[] NOT NULL,
+        -- bid_depth[i] and ask_depth[i] are within depth_bands_bps[i] of mid
+        depth_bands_bps DOUBLE PRECISION[] NOT NULL,
+        bid_depth DOUBLE PRECISION[] NOT NULL,
+        ask_depth DOUBLE PRECISION[] NOT NULL,
+        bid_slope DOUBLE PRECISION NOT NULL,
+        ask_slope DOUBLE PRECISION NOT NULL,
+        bid_levels INTEGER NOT NULL,
+        ask_levels INTEGER NOT NULL,
+        bid_gap_bps DOUBLE PRECISION NOT NULL,
+        ask_gap_bps DOUBLE PRECISION NOT NULL
+    );
+
+
+
+CREATE INDEX IF NOT EXISTS idx_orderbook_metrics_symbol_ts ON orderbook_metrics (market, symbol, event_time DESC);
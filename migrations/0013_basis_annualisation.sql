@@ -0,0 +1 @@
+COMMENT ON COLUMN basis.annualised_basis IS 'basis / spot_mid * 1095: the premium as if it were paid as funding every 8h period (3 x 365) for a year. Not a term-structure yield; symbols on 4h funding are understated by half.';
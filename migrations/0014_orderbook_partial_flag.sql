@@ -0,0 +1,5 @@
+-- partial depth streams (depth5, depth20, ...) store whole top of book snapshots, diff
+-- streams only the levels that changed. NULL on rows captured before this migration,
+-- the stream isn't stored so they can't be told apart (see impact --include-legacy).
+ALTER TABLE orderbook_updates
+ADD COLUMN IF NOT EXISTS partial BOOLEAN;
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rust_decimal::Decimal;
+use tokio::sync::broadcast;
+use tracing::debug;
+
+use crate::market::Market;
+use crate::types::AggTradeData;
+
+/// How trades are grouped into bars, e.g. `time:250ms`, `volume:10`, `dollar:1000000`
+/// or `tick:500`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarSpec {
+    Time { ms: i64 },
+    // base asset quantity
+    Volume(Decimal),
+    // quote asset notional
+    Dollar(Decimal),
+    // aggregate trades
+    Tick(i64),
+}
+
+fn parse_duration_ms(s: &str) -> Result<i64, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in '{s}' (expected ms, s, m or h)"))?;
+    let (n, unit) = s.split_at(split);
+    let n: i64 = n.parse().map_err(|_| format!("invalid duration '{s}'"))?;
+
+    let ms = match unit {
+        "ms" => n,
+        "s" => n * 1_000,
+        "m" => n * 60_000,
+        "h" => n * 3_600_000,
+        _ => return Err(format!("unknown unit in '{s}' (expected ms, s, m or h)")),
+    };
+
+    if ms <= 0 {
+        return Err(format!("bar interval must be positive, got '{s}'"));
+    }
+    Ok(ms)
+}
+
+fn parse_threshold(s: &str) -> Result<Decimal, String> {
+    let v = Decimal::from_str(s).map_err(|_| format!("invalid bar threshold '{s}'"))?;
+    if v <= Decimal::ZERO {
+        return Err(format!("bar threshold must be positive, got '{s}'"));
+    }
+    Ok(v)
+}
+
+impl FromStr for BarSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected <kind>:<size>, got '{s}'"))?;
+
+        match kind {
+            "time" => Ok(Self::Time {
+                ms: parse_duration_ms(value)?,
+            }),
+            "volume" => Ok(Self::Volume(parse_threshold(value)?)),
+            "dollar" => Ok(Self::Dollar(parse_threshold(value)?)),
+            "tick" => match value.parse() {
+                Ok(n) if n > 0 => Ok(Self::Tick(n)),
+                _ => Err(format!(
+                    "tick bars need a positive trade count, got '{value}'"
+                )),
+            },
+            _ => Err(format!(
+                "unknown bar kind '{kind}' (expected time, volume, dollar or tick)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BarSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Time { ms } if ms % 3_600_000 == 0 => write!(f, "time:{}h", ms / 3_600_000),
+            Self::Time { ms } if ms % 60_000 == 0 => write!(f, "time:{}m", ms / 60_000),
+            Self::Time { ms } if ms % 1_000 == 0 => write!(f, "time:{}s", ms / 1_000),
+            Self::Time { ms } => write!(f, "time:{ms}ms"),
+            Self::Volume(v) => write!(f, "volume:{}", v.normalize()),
+            Self::Dollar(v) => write!(f, "dollar:{}", v.normalize()),
+            Self::Tick(n) => write!(f, "tick:{n}"),
+        }
+    }
+}
+
+/// A completed bar. Time bars span `[open_time, close_time)` on trade time, the others
+/// run from their first trade's time to their last.
+#[derive(Debug, Clone)]
+pub struct Bar {
+    pub market: Market,
+    pub symbol: String,
+    pub spec: BarSpec,
+    pub open_time: i64,
+    pub close_time: i64,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub quote_volume: Decimal,
+    // volume where the buyer was the taker
+    pub buy_volume: Decimal,
+    pub agg_trades: i64,
+    pub first_agg_id: i64,
+    pub last_agg_id: i64,
+}
+
+impl Bar {
+    fn start(market: Market, spec: BarSpec, open_time: i64, trade: &AggTradeData) -> Self {
+        let mut bar = Self {
+            market,
+            symbol: trade.s.clone(),
+            spec,
+            open_time,
+            close_time: trade.t,
+            open: trade.p,
+            high: trade.p,
+            low: trade.p,
+            close: trade.p,
+            volume: Decimal::ZERO,
+            quote_volume: Decimal::ZERO,
+            buy_volume: Decimal::ZERO,
+            agg_trades: 0,
+            first_agg_id: trade.a,
+            last_agg_id: trade.a,
+        };
+        bar.add(trade);
+        bar
+    }
+
+    fn add(&mut self, trade: &AggTradeData) {
+        self.high = self.high.max(trade.p);
+        self.low = self.low.min(trade.p);
+        self.close = trade.p;
+        self.volume += trade.q;
+        self.quote_volume += trade.p * trade.q;
+        if !trade.m {
+            self.buy_volume += trade.q;
+        }
+        self.agg_trades += 1;
+        self.last_agg_id = trade.a;
+        if !matches!(self.spec, BarSpec::Time { .. }) {
+            self.close_time = trade.t;
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        match self.spec {
+            BarSpec::Time { .. } => false,
+            BarSpec::Volume(v) => self.volume >= v,
+            BarSpec::Dollar(v) => self.quote_volume >= v,
+            BarSpec::Tick(n) => self.agg_trades >= n,
+        }
+    }
+}
+
+struct Builder {
+    spec: BarSpec,
+    current: Option<Bar>,
+    // time bars: trades before this belong to a bucket that was already emitted
+    closed_until: i64,
+}
+
+impl Builder {
+    /// Whether `trade` falls in a time bucket that has already been emitted
+    fn is_late(&self, trade: &AggTradeData) -> bool {
+        match self.spec {
+            BarSpec::Time { ms } => {
+                let bucket = trade.t - trade.t.rem_euclid(ms);
+                bucket < self.closed_until
+                    || self
+                        .current
+                        .as_ref()
+                        .is_some_and(|bar| bucket < bar.open_time)
+            }
+            _ => false,
+        }
+    }
+
+    /// Feed one trade that is not late, pushing any bar it completes
+    fn push(&mut self, market: Market, trade: &AggTradeData, out: &mut Vec<Bar>) {
+        match self.spec {
+            BarSpec::Time { ms } => {
+                let bucket = trade.t - trade.t.rem_euclid(ms);
+
+                match &mut self.current {
+                    Some(bar) if bucket == bar.open_time => bar.add(trade),
+                    current => {
+                        // a trade in a later bucket closes the open one, empty buckets emit nothing
+                        if let Some(done) = current.take() {
+                            self.closed_until = done.close_time;
+                            out.push(done);
+                        }
+                        let mut bar = Bar::start(market, self.spec, bucket, trade);
+                        bar.close_time = bucket + ms;
+                        *current = Some(bar);
+                    }
+                }
+            }
+            _ => {
+                match &mut self.current {
+                    Some(bar) => bar.add(trade),
+                    None => self.current = Some(Bar::start(market, self.spec, trade.t, trade)),
+                }
+
+                // a trade is never split, the one crossing the threshold closes the bar
+                if self.current.as_ref().is_some_and(Bar::is_full) {
+                    out.extend(self.current.take());
+                }
+            }
+        }
+    }
+
+    /// Emit an open time bar whose bucket ended at or before `now`
+    fn close_expired(&mut self, now: i64, out: &mut Vec<Bar>) {
+        if matches!(self.spec, BarSpec::Time { .. })
+            && let Some(bar) = self.current.take_if(|bar| bar.close_time <= now)
+        {
+            self.closed_until = bar.close_time;
+            out.push(bar);
+        }
+    }
+}
+
+struct SymbolBars {
+    last_agg_id: Option<i64>,
+    builders: Vec<Builder>,
+}
+
+/// Builds bars for every configured spec from the aggTrade stream of each symbol.
+///
+/// Aggregate trade ids are strictly increasing per symbol, so a trade at or below the last
+/// id seen (a replay or an overlapping connection) or one landing in an already closed time
+/// bucket is dropped and counted. A dropped trade goes into no bar of any spec, so volume,
+/// dollar and tick bars cover exactly the trades the time bars do.
+///
+/// A time bar closes on the first trade of a later bucket, or from `close_expired` once its
+/// bucket has passed on the wall clock so quiet symbols still get their last bar.
+pub struct BarAggregator {
+    specs: Vec<BarSpec>,
+    symbols: Mutex<HashMap<(Market, String), SymbolBars>>,
+    late: AtomicU64,
+    tx: broadcast::Sender<Bar>,
+}
+
+impl BarAggregator {
+    pub fn new(specs: Vec<BarSpec>, capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+
+        Self {
+            specs,
+            symbols: Mutex::new(HashMap::new()),
+            late: AtomicU64::new(0),
+            tx,
+        }
+    }
+
+    /// Completed bars as they are emitted. Slow receivers see `Lagged` rather than
+    /// holding up ingestion.
+    pub fn subscribe(&self) -> broadcast::Receiver<Bar> {
+        self.tx.subscribe()
+    }
+
+    /// Apply `trade` and return the bars it completed, which are also broadcast.
+    pub fn on_trade(&self, market: Market, trade: &AggTradeData) -> Vec<Bar> {
+        let mut completed = Vec::new();
+
+        {
+            let mut symbols = self.symbols.lock().unwrap();
+            let state = symbols
+                .entry((market, trade.s.clone()))
+                .or_insert_with(|| SymbolBars {
+                    last_agg_id: None,
+                    builders: self
+                        .specs
+                        .iter()
+                        .map(|spec| Builder {
+                            spec: *spec,
+                            current: None,
+                            closed_until: i64::MIN,
+                        })
+                        .collect(),
+                });
+
+            if state.last_agg_id.is_some_and(|last| trade.a <= last) {
+                let late = self.late.fetch_add(1, Ordering::Relaxed) + 1;
+                debug!(
+                    "Dropped out of order trade {} for {} (last {:?}, {} dropped so far)",
+                    trade.a, trade.s, state.last_agg_id, late
+                );
+                return completed;
+            }
+            state.last_agg_id = Some(trade.a);
+
+            if let Some(builder) = state.builders.iter().find(|b| b.is_late(trade)) {
+                self.late.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "Dropped trade {} for {} behind the closed {} bar",
+                    trade.a, trade.s, builder.spec
+                );
+                return completed;
+            }
+
+            for builder in &mut state.builders {
+                builder.push(market, trade, &mut completed);
+            }
+        }
+
+        self.publish(&completed);
+        completed
+    }
+
+    /// Close time bars whose bucket ended more than `grace_ms` before `now` (unix ms) and
+    /// return them, also broadcast. The grace leaves room for trades still in flight.
+    pub fn close_expired(&self, now: i64, grace_ms: i64) -> Vec<Bar> {
+        let mut completed = Vec::new();
+
+        {
+            let mut symbols = self.symbols.lock().unwrap();
+            for state in symbols.values_mut() {
+                for builder in &mut state.builders {
+                    builder.close_expired(now - grace_ms, &mut completed);
+                }
+            }
+        }
+
+        self.publish(&completed);
+        completed
+    }
+
+    fn publish(&self, bars: &[Bar]) {
+        for bar in bars {
+            // no subscribers is fine
+            let _ = self.tx.send(bar.clone());
+        }
+    }
+
+    pub fn late_trades(&self) -> u64 {
+        self.late.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::market::Market;
+
+// perps converge on spot through funding, every 8 hours on most symbols. The annualised
+// basis is the premium paid once per period for a year, see the column comment on
+// basis.annualised_basis
+const FUNDING_PERIODS_PER_YEAR: f64 = 365.0 * 3.0;
+
+#[derive(Clone, Copy)]
+struct Quote {
+    price: f64,
+    // local receive time, spot and perp event clocks aren't comparable
+    at: i64,
+}
+
+/// Welford running mean and variance, numerically stable over long sessions
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, x: f64) {
+        if self.count == 0 {
+            self.min = x;
+            self.max = x;
+        }
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Sample standard deviation, zero until there are two observations
+    pub fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        (self.m2 / (self.count - 1) as f64).sqrt()
+    }
+
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+}
+
+#[derive(Default)]
+struct Legs {
+    spot: Option<Quote>,
+    perp: Option<Quote>,
+    mark: Option<Quote>,
+    last_bps: f64,
+    stats: RunningStats,
+}
+
+/// One spot/perp observation on the sampling clock
+pub struct BasisSample {
+    pub ts: i64,
+    pub symbol: String,
+    pub spot_mid: f64,
+    pub perp_mid: f64,
+    // absent until the first markPrice for the symbol
+    pub mark_price: Option<f64>,
+    pub basis: f64,
+    pub basis_bps: f64,
+    pub mark_basis_bps: Option<f64>,
+    pub annualised_basis: f64,
+}
+
+pub struct BasisSummary {
+    pub symbol: String,
+    pub last_bps: f64,
+    pub stats: RunningStats,
+}
+
+/// Joins spot mid with USD-M perp mid and mark price per symbol. Legs are updated as
+/// bookTicker and depth messages arrive and read together by `sample` on a fixed clock, so
+/// every row compares prices observed within `max_age_ms` of each other.
+pub struct BasisMonitor {
+    max_age_ms: i64,
+    legs: Mutex<HashMap<String, Legs>>,
+}
+
+impl BasisMonitor {
+    pub fn new(max_age_ms: i64) -> Self {
+        Self {
+            max_age_ms,
+            legs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Mid from bookTicker, a partial depth snapshot or a synced local book, received at `at`
+    /// (unix ms). Raw diff events only carry changed levels and must not be passed here.
+    pub fn on_mid(&self, market: Market, symbol: &str, at: i64, mid: f64) {
+        if !mid.is_finite() || mid <= 0.0 {
+            return;
+        }
+
+        let quote = Some(Quote { price: mid, at });
+        let mut legs = self.legs.lock().unwrap();
+
+        // COIN-M symbols (BTCUSD_PERP) have no spot counterpart of the same name
+        match market {
+            Market::Spot => legs.entry(symbol.to_string()).or_default().spot = quote,
+            Market::UsdM => legs.entry(symbol.to_string()).or_default().perp = quote,
+            Market::CoinM => {}
+        }
+    }
+
+    pub fn on_mark(&self, market: Market, symbol: &str, at: i64, mark: f64) {
+        if market != Market::UsdM || !mark.is_finite() {
+            return;
+        }
+
+        let mut legs = self.legs.lock().unwrap();
+        legs.entry(symbol.to_string()).or_default().mark = Some(Quote { price: mark, at });
+    }
+
+    /// Basis for every symbol with fresh spot and perp legs at `now` (unix ms)
+    pub fn sample(&self, now: i64) -> Vec<BasisSample> {
+        let fresh = |q: Option<Quote>| q.filter(|q| now - q.at <= self.max_age_ms);
+
+        let mut legs = self.legs.lock().unwrap();
+        let mut samples = Vec::new();
+
+        for (symbol, leg) in legs.iter_mut() {
+            let (Some(spot), Some(perp)) = (fresh(leg.spot), fresh(leg.perp)) else {
+                continue;
+            };
+            let mark = fresh(leg.mark);
+
+            let basis = perp.price - spot.price;
+            let basis_bps = basis / spot.price * 10_000.0;
+            let mark_basis_bps = mark.map(|m| (m.price - spot.price) / spot.price * 10_000.0);
+
+            leg.last_bps = basis_bps;
+            leg.stats.push(basis_bps);
+
+            samples.push(BasisSample {
+                ts: now,
+                symbol: symbol.clone(),
+                spot_mid: spot.price,
+                perp_mid: perp.price,
+                mark_price: mark.map(|m| m.price),
+                basis,
+                basis_bps,
+                mark_basis_bps,
+                annualised_basis: basis / spot.price * FUNDING_PERIODS_PER_YEAR,
+            });
+        }
+
+        samples
+    }
+
+    /// Live statistics of `basis_bps` per symbol since startup
+    pub fn summaries(&self) -> Vec<BasisSummary> {
+        let legs = self.legs.lock().unwrap();
+
+        let mut out: Vec<BasisSummary> = legs
+            .iter()
+            .filter(|(_, leg)| leg.stats.count() > 0)
+            .map(|(symbol, leg)| BasisSummary {
+                symbol: symbol.clone(),
+                last_bps: leg.last_bps,
+                stats: leg.stats,
+            })
+            .collect();
+
+        out.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        out
+    }
+}
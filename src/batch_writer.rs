@@ -0,0 +1,443 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use rust_decimal::Decimal;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Duration, MissedTickBehavior, interval, sleep};
+use tracing::{debug, error, info, warn};
+
+use crate::book_metrics::BookMetrics;
+use crate::db_controller::Database;
+use crate::market::Market;
+use crate::types::{AggTradeData, BookTickerData, DepthUpdateData};
+
+// rejected COPY attempts before a batch is written row by row instead
+const MAX_COPY_RETRIES: u32 = 5;
+// pending rows, in batches, before the writer stops taking new ones
+const MAX_PENDING_BATCHES: usize = 20;
+
+const RETRY_INITIAL: Duration = Duration::from_millis(250);
+const RETRY_MAX: Duration = Duration::from_secs(10);
+
+enum WriteRow {
+    Trade(Market, Option<Decimal>, AggTradeData),
+    // gap before, partial depth
+    Book(Market, Option<Decimal>, DepthUpdateData, bool, bool),
+    Ticker(Market, BookTickerData),
+    Metrics(Vec<usize>, Vec<f64>, Arc<BookMetrics>),
+}
+
+/// Accumulates trades, depth, top-of-book updates and book metrics in memory and flushes them with
+/// binary COPY once `batch_size` rows are pending or `flush_interval` has passed.
+#[derive(Clone)]
+pub struct BatchWriter {
+    tx: mpsc::Sender<WriteRow>,
+    close: mpsc::Sender<oneshot::Sender<()>>,
+}
+
+impl BatchWriter {
+    pub fn spawn(db: Database, batch_size: usize, flush_interval: Duration) -> Self {
+        let (tx, rx) = mpsc::channel(batch_size.max(1) * 2);
+        let (close, close_rx) = mpsc::channel(1);
+
+        tokio::spawn(run_writer(
+            db,
+            rx,
+            close_rx,
+            batch_size.max(1),
+            flush_interval,
+        ));
+
+        info!(
+            "Batch writer started (batch size {}, flush every {} ms)",
+            batch_size,
+            flush_interval.as_millis()
+        );
+
+        Self { tx, close }
+    }
+
+    /// Stop taking rows, write everything already queued and wait until it is stored
+    pub async fn close(&self) {
+        let (done, stored) = oneshot::channel();
+        if self.close.send(done).await.is_ok() {
+            let _ = stored.await;
+        }
+    }
+
+    pub async fn write_trade(
+        &self,
+        market: Market,
+        contract_size: Option<Decimal>,
+        trade: AggTradeData,
+    ) -> anyhow::Result<()> {
+        self.tx
+            .send(WriteRow::Trade(market, contract_size, trade))
+            .await
+            .map_err(|_| anyhow::anyhow!("batch writer stopped"))
+    }
+
+    pub async fn write_book_ticker(
+        &self,
+        market: Market,
+        ticker: BookTickerData,
+    ) -> anyhow::Result<()> {
+        self.tx
+            .send(WriteRow::Ticker(market, ticker))
+            .await
+            .map_err(|_| anyhow::anyhow!("batch writer stopped"))
+    }
+
+    pub async fn write_book_update(
+        &self,
+        market: Market,
+        contract_size: Option<Decimal>,
+        update: DepthUpdateData,
+        gap_before: bool,
+        partial: bool,
+    ) -> anyhow::Result<()> {
+        self.tx
+            .send(WriteRow::Book(
+                market,
+                contract_size,
+                update,
+                gap_before,
+                partial,
+            ))
+            .await
+            .map_err(|_| anyhow::anyhow!("batch writer stopped"))
+    }
+
+    pub async fn write_book_metrics(
+        &self,
+        levels: &[usize],
+        bands_bps: &[f64],
+        metrics: Arc<BookMetrics>,
+    ) -> anyhow::Result<()> {
+        self.tx
+            .send(WriteRow::Metrics(
+                levels.to_vec(),
+                bands_bps.to_vec(),
+                metrics,
+            ))
+            .await
+            .map_err(|_| anyhow::anyhow!("batch writer stopped"))
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    trades: Vec<(Market, Option<Decimal>, AggTradeData)>,
+    books: Vec<(Market, Option<Decimal>, DepthUpdateData, bool, bool)>,
+    tickers: Vec<(Market, BookTickerData)>,
+    metrics: Vec<(Vec<usize>, Vec<f64>, Arc<BookMetrics>)>,
+    // rows that will be written, counting every book level
+    rows: usize,
+    // consecutive failed flushes, failed batches stay pending until written
+    failures: u32,
+    // consecutive flushes the database rejected, as opposed to not reaching it
+    rejections: u32,
+    retry_at: Option<Instant>,
+}
+
+/// What went wrong during one flush
+#[derive(Default)]
+struct Outcome {
+    // the database refused the data, e.g. a constraint or a malformed value
+    rejected: bool,
+    // the database wasn't reached: connection, pool or IO errors
+    unavailable: bool,
+}
+
+impl Outcome {
+    fn record(&mut self, e: &sqlx::Error) {
+        if matches!(e, sqlx::Error::Database(_)) {
+            self.rejected = true;
+        } else {
+            self.unavailable = true;
+        }
+    }
+
+    fn failed(&self) -> bool {
+        self.rejected || self.unavailable
+    }
+}
+
+/// Account for one row-by-row insert. A row the database rejects is logged and dropped;
+/// any other error means the pass should stop, returned as `false`, so the remaining rows
+/// stay pending.
+fn settle(
+    result: Result<(), sqlx::Error>,
+    outcome: &mut Outcome,
+    dropped: &mut usize,
+    describe: impl FnOnce() -> String,
+) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e @ sqlx::Error::Database(_)) => {
+            error!("Dropping {}: {}", describe(), e);
+            *dropped += 1;
+            true
+        }
+        Err(e) => {
+            outcome.record(&e);
+            false
+        }
+    }
+}
+
+impl Pending {
+    fn push(&mut self, row: WriteRow) {
+        match row {
+            WriteRow::Trade(market, contract_size, trade) => {
+                self.trades.push((market, contract_size, trade));
+                self.rows += 1;
+            }
+            WriteRow::Book(market, contract_size, update, gap_before, partial) => {
+                self.rows += 1 + update.b.len() + update.a.len();
+                self.books
+                    .push((market, contract_size, update, gap_before, partial));
+            }
+            WriteRow::Ticker(market, ticker) => {
+                self.tickers.push((market, ticker));
+                self.rows += 1;
+            }
+            WriteRow::Metrics(levels, bands_bps, metrics) => {
+                self.metrics.push((levels, bands_bps, metrics));
+                self.rows += 1;
+            }
+        }
+    }
+
+    /// COPY everything pending. A table whose COPY fails keeps its rows and is retried
+    /// with backoff. Once the database has rejected a batch `MAX_COPY_RETRIES` times in a
+    /// row, its rows are inserted one by one so a single bad row can't hold the batch back.
+    /// While the database can't be reached nothing is dropped, the rows wait for it.
+    /// `force` ignores the backoff.
+    async fn flush(&mut self, db: &Database, force: bool) {
+        if self.rows == 0 {
+            return;
+        }
+        if !force && self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return;
+        }
+
+        let started = Instant::now();
+        let (trades, books, tickers, metrics) = (
+            self.trades.len(),
+            self.books.len(),
+            self.tickers.len(),
+            self.metrics.len(),
+        );
+        let mut outcome = Outcome::default();
+
+        if self.rejections >= MAX_COPY_RETRIES {
+            warn!(
+                "COPY rejected {} times, inserting {} pending rows individually",
+                self.rejections, self.rows
+            );
+            self.insert_rows(db, &mut outcome).await;
+        } else {
+            match db.copy_trades(&self.trades).await {
+                Ok(_) => self.trades.clear(),
+                Err(e) => {
+                    error!("Trade batch of {} rows failed: {}", trades, e);
+                    outcome.record(&e);
+                }
+            }
+
+            match db.copy_book_updates(&self.books).await {
+                Ok(_) => self.books.clear(),
+                Err(e) => {
+                    error!("Depth batch of {} updates failed: {}", books, e);
+                    outcome.record(&e);
+                }
+            }
+
+            match db.copy_book_tickers(&self.tickers).await {
+                Ok(_) => self.tickers.clear(),
+                Err(e) => {
+                    error!("Book ticker batch of {} rows failed: {}", tickers, e);
+                    outcome.record(&e);
+                }
+            }
+
+            match db.copy_book_metrics(&self.metrics).await {
+                Ok(_) => self.metrics.clear(),
+                Err(e) => {
+                    error!("Book metrics batch of {} rows failed: {}", metrics, e);
+                    outcome.record(&e);
+                }
+            }
+        }
+
+        self.rows = self.trades.len()
+            + self.tickers.len()
+            + self.metrics.len()
+            + self
+                .books
+                .iter()
+                .map(|(_, _, u, _, _)| 1 + u.b.len() + u.a.len())
+                .sum::<usize>();
+
+        if outcome.failed() {
+            let delay = RETRY_INITIAL
+                .saturating_mul(2u32.saturating_pow(self.failures))
+                .min(RETRY_MAX);
+            self.failures = self.failures.saturating_add(1);
+            if outcome.rejected {
+                self.rejections += 1;
+            }
+            self.retry_at = Some(Instant::now() + delay);
+            warn!(
+                "Keeping {} rows for retry in {} ms (attempt {})",
+                self.rows,
+                delay.as_millis(),
+                self.failures
+            );
+            return;
+        }
+
+        self.failures = 0;
+        self.rejections = 0;
+        self.retry_at = None;
+
+        debug!(
+            "Flushed {} trades, {} depth updates, {} book tickers and {} book metrics in {} ms",
+            trades,
+            books,
+            tickers,
+            metrics,
+            started.elapsed().as_millis()
+        );
+    }
+
+    /// Fallback for batches COPY keeps rejecting. Rows rejected on their own are logged
+    /// and dropped, the rest are written. Stops at the first error that isn't a rejection.
+    async fn insert_rows(&mut self, db: &Database, outcome: &mut Outcome) {
+        let mut dropped = 0usize;
+
+        let mut done = 0;
+        for (market, contract_size, trade) in &self.trades {
+            let result = db.insert_trade(*market, *contract_size, trade).await;
+            if !settle(result, outcome, &mut dropped, || {
+                format!("trade {} for {}", trade.a, trade.s)
+            }) {
+                break;
+            }
+            done += 1;
+        }
+        self.trades.drain(..done);
+
+        let mut done = 0;
+        for (market, contract_size, update, gap_before, partial) in &self.books {
+            if outcome.unavailable {
+                break;
+            }
+            let result = db
+                .insert_book_update(*market, *contract_size, update, *gap_before, *partial)
+                .await;
+            if !settle(result, outcome, &mut dropped, || {
+                format!("depth update {} for {}", update.u2, update.s)
+            }) {
+                break;
+            }
+            done += 1;
+        }
+        self.books.drain(..done);
+
+        let mut done = 0;
+        for (market, ticker) in &self.tickers {
+            if outcome.unavailable {
+                break;
+            }
+            let result = db.insert_book_ticker(*market, ticker).await;
+            if !settle(result, outcome, &mut dropped, || {
+                format!("book ticker for {}", ticker.s)
+            }) {
+                break;
+            }
+            done += 1;
+        }
+        self.tickers.drain(..done);
+
+        let mut done = 0;
+        for (levels, bands_bps, m) in &self.metrics {
+            if outcome.unavailable {
+                break;
+            }
+            let result = db.insert_book_metrics(levels, bands_bps, m).await;
+            if !settle(result, outcome, &mut dropped, || {
+                format!("book metrics {} for {}", m.last_update_id, m.symbol)
+            }) {
+                break;
+            }
+            done += 1;
+        }
+        self.metrics.drain(..done);
+
+        if dropped > 0 {
+            error!("{} rows could not be written and were dropped", dropped);
+        }
+    }
+
+    /// Final flush on shutdown, retried a few times before giving up on what is left
+    async fn drain(&mut self, db: &Database) {
+        for attempt in 0..=MAX_COPY_RETRIES {
+            self.flush(db, true).await;
+            if self.rows == 0 {
+                return;
+            }
+            sleep(RETRY_INITIAL.saturating_mul(2u32.pow(attempt))).await;
+        }
+
+        error!("{} rows could not be written before shutdown", self.rows);
+    }
+}
+
+async fn run_writer(
+    db: Database,
+    mut rx: mpsc::Receiver<WriteRow>,
+    mut close: mpsc::Receiver<oneshot::Sender<()>>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut pending = Pending::default();
+    let mut ticker = interval(flush_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // past this many pending rows new rows wait in the channel, which pushes back on the
+    // handlers instead of growing without bound while the database is away
+    let max_pending = batch_size.saturating_mul(MAX_PENDING_BATCHES);
+
+    loop {
+        tokio::select! {
+            row = rx.recv(), if pending.rows < max_pending => match row {
+                Some(row) => {
+                    pending.push(row);
+                    if pending.rows >= batch_size {
+                        pending.flush(&db, false).await;
+                    }
+                }
+                None => {
+                    pending.drain(&db).await;
+                    info!("Batch writer exiting (channel closed)");
+                    return;
+                }
+            },
+            Some(done) = close.recv() => {
+                // rows already queued are still written, later writes fail
+                rx.close();
+                while let Some(row) = rx.recv().await {
+                    pending.push(row);
+                }
+
+                pending.drain(&db).await;
+                info!("Batch writer closed");
+                let _ = done.send(());
+                return;
+            }
+            _ = ticker.tick() => pending.flush(&db, false).await,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+
+use crate::data_manip::DepthRow;
+use crate::market::Market;
+
+/// Microstructure snapshot of one depth update. Quantities are in the stream's units
+/// (contracts on COIN-M), prices and distances are computed in f64 like `NativeBook`.
+#[derive(Debug, Clone)]
+pub struct BookMetrics {
+    pub market: Market,
+    pub symbol: String,
+    pub event_time: i64,
+    pub last_update_id: i64,
+    pub mid: f64,
+    pub spread_bps: f64,
+    // top of book mid skewed towards the thinner side
+    pub microprice: f64,
+    // mean of the bid and ask VWAPs over the deepest imbalance level
+    pub weighted_mid: f64,
+    // (bid - ask) / (bid + ask) quantity over the top N levels, one per configured N
+    pub imbalance: Vec<f64>,
+    // quantity within X bps of mid, one per configured X
+    pub bid_depth: Vec<f64>,
+    pub ask_depth: Vec<f64>,
+    // cumulative quantity added per bp away from mid, least squares over all levels
+    pub bid_slope: f64,
+    pub ask_slope: f64,
+    pub bid_levels: i32,
+    pub ask_levels: i32,
+    // mean distance between adjacent price levels
+    pub bid_gap_bps: f64,
+    pub ask_gap_bps: f64,
+}
+
+#[derive(Default)]
+struct Side {
+    price: Vec<f64>,
+    quantity: Vec<f64>,
+    cumulative: Vec<f64>,
+    bps: Vec<f64>,
+}
+
+impl Side {
+    fn push(&mut self, row: &DepthRow) {
+        self.price.push(row.price);
+        self.quantity.push(row.quantity);
+        self.cumulative.push(row.cumulative_depth);
+        self.bps.push(row.bps_from_mid);
+    }
+
+    fn top_quantity(&self, n: usize) -> f64 {
+        self.quantity.iter().take(n).sum()
+    }
+
+    fn vwap(&self, n: usize) -> f64 {
+        let (notional, qty) = self
+            .price
+            .iter()
+            .zip(&self.quantity)
+            .take(n)
+            .fold((0.0, 0.0), |(pq, q), (p, x)| (pq + p * x, q + x));
+        notional / qty
+    }
+
+    fn depth_within(&self, bps: f64) -> f64 {
+        self.bps
+            .iter()
+            .zip(&self.quantity)
+            .filter(|(d, _)| d.abs() <= bps)
+            // an empty f64 sum is -0.0
+            .fold(0.0, |depth, (_, q)| depth + q)
+    }
+
+    fn slope(&self) -> f64 {
+        let n = self.bps.len() as f64;
+        if n < 2.0 {
+            return f64::NAN;
+        }
+
+        let xs = self.bps.iter().map(|d| d.abs());
+        let mean_x = xs.clone().sum::<f64>() / n;
+        let mean_y = self.cumulative.iter().sum::<f64>() / n;
+
+        let (cov, var) = xs.zip(&self.cumulative).fold((0.0, 0.0), |(c, v), (x, y)| {
+            (c + (x - mean_x) * (y - mean_y), v + (x - mean_x).powi(2))
+        });
+        cov / var
+    }
+
+    fn mean_gap_bps(&self) -> f64 {
+        if self.bps.len() < 2 {
+            return f64::NAN;
+        }
+        let gaps: f64 = self.bps.windows(2).map(|w| (w[1] - w[0]).abs()).sum();
+        gaps / (self.bps.len() - 1) as f64
+    }
+}
+
+/// Book sides from a `NativeBook` depth profile, best level first. The level 0 rows at
+/// each best price are skipped.
+fn sides(depth: &[DepthRow]) -> (Side, Side) {
+    let mut bids = Side::default();
+    let mut asks = Side::default();
+
+    // the profile ascends in price, so bids come deepest first
+    for row in depth.iter().rev().filter(|r| r.side == 1 && r.level_id > 0) {
+        bids.push(row);
+    }
+    for row in depth.iter().filter(|r| r.side == -1 && r.level_id > 0) {
+        asks.push(row);
+    }
+
+    (bids, asks)
+}
+
+/// Derives `BookMetrics` from each computed depth frame and fans them out to live
+/// consumers.
+pub struct BookMetricsEngine {
+    levels: Vec<usize>,
+    bands_bps: Vec<f64>,
+    tx: broadcast::Sender<Arc<BookMetrics>>,
+}
+
+impl BookMetricsEngine {
+    /// `levels` are the N in imbalance at N levels, `bands_bps` the X in depth within X bps.
+    pub fn new(levels: Vec<usize>, bands_bps: Vec<f64>, capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+
+        Self {
+            levels,
+            bands_bps,
+            tx,
+        }
+    }
+
+    pub fn levels(&self) -> &[usize] {
+        &self.levels
+    }
+
+    pub fn bands_bps(&self) -> &[f64] {
+        &self.bands_bps
+    }
+
+    /// Metrics as they are computed. Slow receivers see `Lagged` rather than holding up
+    /// ingestion.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<BookMetrics>> {
+        self.tx.subscribe()
+    }
+
+    /// Metrics for `depth`, the rows of `NativeBook::rows`. `None` when either side of the
+    /// book is empty.
+    pub fn compute(
+        &self,
+        market: Market,
+        symbol: &str,
+        event_time: i64,
+        last_update_id: i64,
+        depth: &[DepthRow],
+    ) -> Option<Arc<BookMetrics>> {
+        let (bids, asks) = sides(depth);
+
+        let (Some(&best_bid), Some(&best_ask)) = (bids.price.first(), asks.price.first()) else {
+            return None;
+        };
+        let (bid_qty, ask_qty) = (bids.quantity[0], asks.quantity[0]);
+
+        let mid = (best_bid + best_ask) / 2.0;
+        let deepest = self.levels.iter().copied().max().unwrap_or(1);
+
+        let metrics = Arc::new(BookMetrics {
+            market,
+            symbol: symbol.to_string(),
+            event_time,
+            last_update_id,
+            mid,
+            spread_bps: (best_ask - best_bid) / mid * 10_000.0,
+            microprice: (best_ask * bid_qty + best_bid * ask_qty) / (bid_qty + ask_qty),
+            weighted_mid: (bids.vwap(deepest) + asks.vwap(deepest)) / 2.0,
+            imbalance: self
+                .levels
+                .iter()
+                .map(|&n| {
+                    let (b, a) = (bids.top_quantity(n), asks.top_quantity(n));
+                    (b - a) / (b + a)
+                })
+                .collect(),
+            bid_depth: self
+                .bands_bps
+                .iter()
+                .map(|&x| bids.depth_within(x))
+                .collect(),
+            ask_depth: self
+                .bands_bps
+                .iter()
+                .map(|&x| asks.depth_within(x))
+                .collect(),
+            bid_slope: bids.slope(),
+            ask_slope: asks.slope(),
+            bid_levels: bids.price.len() as i32,
+            ask_levels: asks.price.len() as i32,
+            bid_gap_bps: bids.mean_gap_bps(),
+            ask_gap_bps: asks.mean_gap_bps(),
+        });
+
+        // no subscribers is fine
+        let _ = self.tx.send(metrics.clone());
+
+        Some(metrics)
+    }
+}
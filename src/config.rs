@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use clap::Args;
+use serde::Deserialize;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions, PgSslMode};
+use sqlx::{PgPool, Postgres, pool::PoolOptions};
+
+/// Database connection flags. Each one falls back to its environment variable,
+/// then to the `[database]` section of `--config`, then to the local dev defaults. Settings
+/// carried by the url (database name, sslmode) apply unless set explicitly.
+#[derive(Args, Debug, Clone)]
+pub struct DbArgs {
+    #[arg(long, env = "DATABASE_URL", hide_env_values = true)]
+    pub db_url: Option<String>,
+
+    #[arg(long, env = "PGHOST")]
+    pub db_host: Option<String>,
+
+    #[arg(long, env = "PGPORT")]
+    pub db_port: Option<u16>,
+
+    #[arg(long, env = "PGUSER")]
+    pub db_user: Option<String>,
+
+    #[arg(long, env = "PGPASSWORD", hide_env_values = true)]
+    pub db_password: Option<String>,
+
+    #[arg(long, env = "PGPASSWORD_FILE")]
+    pub db_password_file: Option<PathBuf>,
+
+    #[arg(long, env = "PGDATABASE")]
+    pub db_name: Option<String>,
+
+    #[arg(long)]
+    pub db_pool_size: Option<u32>,
+
+    // disable, allow, prefer, require, verify-ca or verify-full
+    #[arg(long, env = "PGSSLMODE")]
+    pub db_ssl_mode: Option<String>,
+
+    #[arg(long)]
+    pub db_statement_timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct DbFileConfig {
+    url: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    user: Option<String>,
+    password: Option<String>,
+    password_file: Option<PathBuf>,
+    name: Option<String>,
+    pool_size: Option<u32>,
+    ssl_mode: Option<String>,
+    statement_timeout_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    #[serde(default)]
+    database: DbFileConfig,
+}
+
+impl FileConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        toml::from_str(&raw).with_context(|| format!("parsing config file {}", path.display()))
+    }
+}
+
+/// Fully resolved connection settings
+#[derive(Clone)]
+pub struct DbConfig {
+    url: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    user: Option<String>,
+    password: Option<String>,
+    pub name: String,
+    pool_size: u32,
+    ssl_mode: Option<PgSslMode>,
+    statement_timeout_ms: Option<u64>,
+}
+
+impl DbConfig {
+    pub fn resolve(args: &DbArgs, file: &FileConfig) -> anyhow::Result<Self> {
+        let f = &file.database;
+
+        let password = match (&args.db_password, &args.db_password_file) {
+            (Some(p), _) => Some(p.clone()),
+            (None, Some(path)) => Some(read_password_file(path)?),
+            (None, None) => match (&f.password, &f.password_file) {
+                (Some(p), _) => Some(p.clone()),
+                (None, Some(path)) => Some(read_password_file(path)?),
+                // keep the old local dev credentials unless a url carries its own
+                (None, None) if args.db_url.is_none() && f.url.is_none() => Some("admin".into()),
+                (None, None) => None,
+            },
+        };
+
+        let ssl_mode = args
+            .db_ssl_mode
+            .clone()
+            .or_else(|| f.ssl_mode.clone())
+            .map(|m| PgSslMode::from_str(&m))
+            .transpose()
+            .context("invalid ssl mode")?;
+
+        let url = args.db_url.clone().or_else(|| f.url.clone());
+
+        // a database named in the url is used unless one is set explicitly
+        let url_name = url
+            .as_deref()
+            .map(PgConnectOptions::from_str)
+            .transpose()
+            .context("invalid database url")?
+            .and_then(|opts| opts.get_database().map(str::to_string));
+
+        Ok(Self {
+            url,
+            host: args.db_host.clone().or_else(|| f.host.clone()),
+            port: args.db_port.or(f.port),
+            user: args.db_user.clone().or_else(|| f.user.clone()),
+            password,
+            name: args
+                .db_name
+                .clone()
+                .or_else(|| f.name.clone())
+                .or(url_name)
+                .unwrap_or_else(|| "test_crypto_pricing".into()),
+            pool_size: args.db_pool_size.or(f.pool_size).unwrap_or(10),
+            ssl_mode,
+            statement_timeout_ms: args.db_statement_timeout_ms.or(f.statement_timeout_ms),
+        })
+    }
+
+    /// Options for `database`; a url, when given, supplies anything not set explicitly.
+    pub fn connect_options(&self, database: &str) -> Result<PgConnectOptions, sqlx::Error> {
+        let mut opts = match &self.url {
+            Some(url) => PgConnectOptions::from_str(url)?,
+            None => PgConnectOptions::new_without_pgpass()
+                .host("localhost")
+                .port(5432)
+                .username("postgres"),
+        };
+
+        if let Some(host) = &self.host {
+            opts = opts.host(host);
+        }
+        if let Some(port) = self.port {
+            opts = opts.port(port);
+        }
+        if let Some(user) = &self.user {
+            opts = opts.username(user);
+        }
+        if let Some(password) = &self.password {
+            opts = opts.password(password);
+        }
+
+        if let Some(ms) = self.statement_timeout_ms {
+            opts = opts.options([("statement_timeout", ms.to_string())]);
+        }
+
+        // the url's sslmode stands unless one is set explicitly
+        opts = match (self.ssl_mode, &self.url) {
+            (Some(mode), _) => opts.ssl_mode(mode),
+            (None, None) => opts.ssl_mode(PgSslMode::Prefer),
+            (None, Some(_)) => opts,
+        };
+
+        Ok(opts.database(database))
+    }
+
+    fn pool_options(&self) -> PoolOptions<Postgres> {
+        PgPoolOptions::new().max_connections(self.pool_size)
+    }
+
+    pub async fn connect_pool(&self, database: &str) -> Result<PgPool, sqlx::Error> {
+        self.pool_options()
+            .connect_with(self.connect_options(database)?)
+            .await
+    }
+}
+
+fn read_password_file(path: &Path) -> anyhow::Result<String> {
+    let raw = std::fs::read_to_string(path)
+        .with_context(|| format!("reading password file {}", path.display()))?;
+    Ok(raw.trim_end_matches(['\n', '\r']).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(url: Option<&str>, name: Option<&str>) -> DbArgs {
+        DbArgs {
+            db_url: url.map(str::to_string),
+            db_host: None,
+            db_port: None,
+            db_user: None,
+            db_password: None,
+            db_password_file: None,
+            db_name: name.map(str::to_string),
+            db_pool_size: None,
+            db_ssl_mode: None,
+            db_statement_timeout_ms: None,
+        }
+    }
+
+    fn resolve(url: Option<&str>, name: Option<&str>) -> DbConfig {
+        DbConfig::resolve(&args(url, name), &FileConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn database_comes_from_the_url() {
+        let config = resolve(Some("postgres://app@db:5432/prod_db"), None);
+        assert_eq!(config.name, "prod_db");
+    }
+
+    #[test]
+    fn explicit_database_overrides_the_url() {
+        let config = resolve(Some("postgres://app@db:5432/prod_db"), Some("replay"));
+        assert_eq!(config.name, "replay");
+    }
+
+    #[test]
+    fn dev_default_without_a_named_database() {
+        assert_eq!(resolve(None, None).name, "test_crypto_pricing");
+        assert_eq!(
+            resolve(Some("postgres://app@db:5432"), None).name,
+            "test_crypto_pricing"
+        );
+    }
+
+    #[test]
+    fn url_sslmode_is_kept() {
+        let config = resolve(Some("postgres://app@db/prod_db?sslmode=require"), None);
+        let opts = config.connect_options(&config.name).unwrap();
+        assert!(matches!(opts.get_ssl_mode(), PgSslMode::Require));
+        assert_eq!(opts.get_database(), Some("prod_db"));
+    }
+}
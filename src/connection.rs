@@ -0,0 +1,782 @@
+use chrono::{DateTime, Utc};
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::{HashMap, VecDeque};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Duration, Instant, sleep, sleep_until, timeout};
+use tracing::{debug, error, info, warn};
+
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message};
+
+use crate::control::{ControlCommand, ControlMethod};
+use crate::market::{Inbound, Market};
+
+type WsRead = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+type WsWrite = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+// retry delay when the replacement socket for a rotation cannot be opened
+const ROTATION_RETRY: Duration = Duration::from_secs(30);
+
+#[derive(Clone)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub idle_timeout: Duration,
+    /// Open a replacement connection after this long; `None` disables rotation
+    pub rotate_after: Option<Duration>,
+    pub rotation_overlap: Duration,
+}
+
+/// Exponential backoff with full jitter over the upper half of the window
+struct Backoff {
+    attempt: u32,
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            attempt: 0,
+            initial,
+            max,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let exp = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        let ceiling = exp.as_millis() as u64;
+        let jittered = rand::rng().random_range(ceiling / 2..=ceiling);
+        Duration::from_millis(jittered)
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+enum SessionEnd {
+    Disconnected(String),
+    ChannelClosed,
+}
+
+enum Frame {
+    Text(String),
+    Ignored,
+    End(String),
+}
+
+fn classify(item: Option<Result<Message, tokio_tungstenite::tungstenite::Error>>) -> Frame {
+    match item {
+        Some(Ok(Message::Text(text))) => Frame::Text(text),
+        Some(Ok(Message::Close(frame))) => Frame::End(
+            frame
+                .map(|f| format!("close frame {} {}", f.code, f.reason))
+                .unwrap_or_else(|| "close frame".into()),
+        ),
+        Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Binary(_) | Message::Frame(_))) => {
+            Frame::Ignored
+        }
+        Some(Err(e)) => Frame::End(e.to_string()),
+        None => Frame::End("stream ended".into()),
+    }
+}
+
+fn forward(tx: &mpsc::Sender<Inbound>, market: Market, raw: String) -> Result<(), SessionEnd> {
+    match tx.try_send(Inbound { market, raw }) {
+        Ok(_) => Ok(()),
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            // queue is full: drop the message and log
+            error!("Inbound queue full — dropping websocket message");
+            Ok(())
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => Err(SessionEnd::ChannelClosed),
+    }
+}
+
+/// Which of the two overlapping connections a frame came from
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Source {
+    Old,
+    Replacement,
+}
+
+/// Rotation progress of one stream
+#[derive(Default)]
+struct StreamHandover {
+    // highest id forwarded so far
+    high: Option<i64>,
+    // the replacement socket has caught up and serves the stream alone from here
+    cut_over: bool,
+    // replacement frames ahead of the old socket, held until the old one catches up
+    ahead: VecDeque<(i64, String)>,
+}
+
+impl StreamHandover {
+    fn forward_if_newer(&mut self, id: i64, raw: String, out: &mut Vec<String>) -> bool {
+        if self.high.is_some_and(|high| id <= high) {
+            return false;
+        }
+        self.high = Some(id);
+        out.push(raw);
+        true
+    }
+
+    fn cut_over(&mut self, out: &mut Vec<String>) -> u64 {
+        self.cut_over = true;
+        let mut dropped = 0;
+        while let Some((id, raw)) = self.ahead.pop_front() {
+            if !self.forward_if_newer(id, raw, out) {
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+}
+
+/// Hands each stream over from the old connection to its replacement while both are open.
+/// The old socket keeps serving a stream until the replacement has delivered an id the old
+/// one already forwarded, then the replacement takes over. Ids at or below a stream's
+/// high-water mark are dropped, so nothing is forwarded twice or out of order.
+///
+/// Key is the update id (`u`) for book streams, `lastUpdateId` for spot partial depth
+/// (which carries no event type), the aggregate trade id (`a`) for trades and the event
+/// time for everything else. Frames without a key come from the old socket
+/// until it closes.
+#[derive(Default)]
+struct Handover {
+    streams: HashMap<String, StreamHandover>,
+    old_closed: bool,
+    dropped: u64,
+}
+
+impl Handover {
+    fn sequence_key(raw: &str) -> Option<(String, i64)> {
+        let msg: Value = serde_json::from_str(raw).ok()?;
+        let stream = msg["stream"].as_str()?.to_string();
+        let data = &msg["data"];
+
+        let id = match data["e"].as_str() {
+            Some("aggTrade") => data["a"].as_i64(),
+            None if data["lastUpdateId"].is_i64() => data["lastUpdateId"].as_i64(),
+            _ => data["u"].as_i64().or_else(|| data["E"].as_i64()),
+        }?;
+
+        Some((stream, id))
+    }
+
+    /// Frames to forward, in order, for `raw` received on `source`
+    fn on_frame(&mut self, source: Source, raw: String, out: &mut Vec<String>) {
+        let Some((stream, id)) = Self::sequence_key(&raw) else {
+            if source == Source::Old || self.old_closed {
+                out.push(raw);
+            }
+            return;
+        };
+
+        let state = self.streams.entry(stream).or_default();
+
+        let forwarded = match source {
+            Source::Old if state.cut_over => false,
+            Source::Old => {
+                let forwarded = state.forward_if_newer(id, raw, out);
+                // the old socket reached the replacement's first held frame
+                if let (Some(high), Some(&(first, _))) = (state.high, state.ahead.front())
+                    && first <= high
+                {
+                    self.dropped += state.cut_over(out);
+                }
+                forwarded
+            }
+            Source::Replacement if state.cut_over => state.forward_if_newer(id, raw, out),
+            Source::Replacement => match state.high {
+                // the replacement is at or behind what was forwarded: it has caught up
+                Some(high) if id <= high => {
+                    self.dropped += state.cut_over(out);
+                    false
+                }
+                _ => {
+                    state.ahead.push_back((id, raw));
+                    true
+                }
+            },
+        };
+
+        if !forwarded {
+            self.dropped += 1;
+        }
+    }
+
+    /// The old socket is gone, every stream is served by the replacement from here
+    fn close_old(&mut self, out: &mut Vec<String>) {
+        self.old_closed = true;
+        for state in self.streams.values_mut() {
+            self.dropped += state.cut_over(out);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    msg: String,
+}
+
+/// Reply to a SUBSCRIBE/UNSUBSCRIBE/LIST_SUBSCRIPTIONS request. Errors come either as
+/// top-level `code`/`msg` or nested under `error` depending on the endpoint.
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    id: u64,
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcError>,
+    #[serde(default)]
+    code: Option<i64>,
+    #[serde(default)]
+    msg: Option<String>,
+}
+
+struct PendingRequest {
+    method: ControlMethod,
+    params: Vec<String>,
+    reply: oneshot::Sender<Result<Value, String>>,
+}
+
+/// Owns the live socket(s) for one market. Keeps the combined-stream connection alive,
+/// reconnecting with backoff whenever it drops, rotating onto a fresh socket ahead of
+/// Binance's 24 hour limit and relaying control commands over the write half.
+pub struct StreamSupervisor {
+    market: Market,
+    base_url: String,
+    active: Vec<String>,
+    tx: mpsc::Sender<Inbound>,
+    commands: Option<mpsc::Receiver<ControlCommand>>,
+    policy: ReconnectPolicy,
+    next_id: u64,
+    pending: HashMap<u64, PendingRequest>,
+}
+
+impl StreamSupervisor {
+    pub fn new(
+        market: Market,
+        streams: Vec<String>,
+        tx: mpsc::Sender<Inbound>,
+        commands: Option<mpsc::Receiver<ControlCommand>>,
+        policy: ReconnectPolicy,
+    ) -> Self {
+        Self {
+            market,
+            base_url: market.ws_url().to_string(),
+            active: streams,
+            tx,
+            commands,
+            policy,
+            next_id: 1,
+            pending: HashMap::new(),
+        }
+    }
+
+    fn url(&self) -> String {
+        if self.active.is_empty() {
+            format!("{}/stream", self.base_url)
+        } else {
+            format!("{}/stream?streams={}", self.base_url, self.active.join("/"))
+        }
+    }
+
+    /// Runs until the inbound queue has been closed.
+    pub async fn run(mut self) {
+        let mut backoff = Backoff::new(self.policy.initial_delay, self.policy.max_delay);
+        let mut outage_start: Option<DateTime<Utc>> = None;
+
+        loop {
+            let url = self.url();
+            info!("Connecting to {}: {}", self.market, url);
+
+            match connect_async(&url).await {
+                Ok((ws_stream, _)) => {
+                    match outage_start.take() {
+                        Some(since) => warn!(
+                            "Reconnected to Binance {} WebSocket, outage window {} -> {} ({:.1}s)",
+                            self.market,
+                            since,
+                            Utc::now(),
+                            (Utc::now() - since).num_milliseconds() as f64 / 1000.0
+                        ),
+                        None => info!("Connected to Binance {} WebSocket.", self.market),
+                    }
+
+                    let (write, read) = ws_stream.split();
+
+                    let (end, received) = self.drive_session(write, read).await;
+                    self.fail_pending("connection lost before acknowledgement");
+
+                    // only treat the session as healthy if data actually flowed
+                    if received > 0 {
+                        backoff.reset();
+                    }
+
+                    match end {
+                        SessionEnd::ChannelClosed => {
+                            error!("Inbound queue closed — stopping reader");
+                            return;
+                        }
+                        SessionEnd::Disconnected(reason) => {
+                            warn!("Connection lost after {} messages: {}", received, reason);
+                        }
+                    }
+
+                    outage_start = Some(Utc::now());
+                }
+                Err(e) => {
+                    error!("WebSocket connect failed: {}", e);
+                    outage_start.get_or_insert_with(Utc::now);
+                }
+            }
+
+            let delay = backoff.next_delay();
+            info!(
+                "Reconnecting in {} ms (attempt {})",
+                delay.as_millis(),
+                backoff.attempt
+            );
+
+            // keep serving control commands while offline, they apply on reconnect
+            let wait = sleep(delay);
+            tokio::pin!(wait);
+            loop {
+                tokio::select! {
+                    _ = &mut wait => break,
+                    cmd = recv_command(&mut self.commands) => self.apply_offline(cmd),
+                }
+            }
+        }
+    }
+
+    async fn drive_session(&mut self, mut write: WsWrite, mut read: WsRead) -> (SessionEnd, u64) {
+        let mut received: u64 = 0;
+        let mut rotate_at = self.policy.rotate_after.map(|d| Instant::now() + d);
+        let idle_timeout = self.policy.idle_timeout;
+
+        loop {
+            let rotation = async move {
+                match rotate_at {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                item = timeout(idle_timeout, read.next()) => {
+                    let frame = match item {
+                        Ok(item) => classify(item),
+                        Err(_) => Frame::End(format!("no frames for {}s", idle_timeout.as_secs())),
+                    };
+
+                    match frame {
+                        Frame::Text(text) => {
+                            received += 1;
+                            if let Err(end) = self.on_text(text) {
+                                return (end, received);
+                            }
+                        }
+                        Frame::Ignored => {}
+                        Frame::End(reason) => return (SessionEnd::Disconnected(reason), received),
+                    }
+                }
+                cmd = recv_command(&mut self.commands) => {
+                    if let Err(e) = self.send_request(&mut write, cmd).await {
+                        return (SessionEnd::Disconnected(format!("control request send failed: {e}")), received);
+                    }
+                }
+                _ = rotation => {
+                    let url = self.url();
+                    // requests acknowledged on the old socket from here on aren't in `url`
+                    let opened_with = self.active.clone();
+                    info!("Rotating connection: opening replacement socket");
+
+                    let (new_write, new_read) = match connect_async(&url).await {
+                        Ok((ws_stream, _)) => ws_stream.split(),
+                        Err(e) => {
+                            warn!("Rotation connect failed, keeping current connection: {}", e);
+                            rotate_at = Some(Instant::now() + ROTATION_RETRY);
+                            continue;
+                        }
+                    };
+
+                    match self.overlap(&mut read, new_read).await {
+                        Ok((new_read, forwarded)) => {
+                            received += forwarded;
+                            read = new_read;
+                            write = new_write;
+                            self.fail_pending("connection rotated before acknowledgement");
+                            rotate_at = self.policy.rotate_after.map(|d| Instant::now() + d);
+                            info!("Rotation complete, old connection dropped");
+
+                            if let Err(e) = self.replay_changes(&mut write, &opened_with).await {
+                                return (SessionEnd::Disconnected(format!("control request send failed: {e}")), received);
+                            }
+                        }
+                        Err(OverlapError::Session(end)) => return (end, received),
+                        Err(OverlapError::ReplacementFailed(reason)) => {
+                            warn!("Replacement socket failed during overlap, keeping current connection: {}", reason);
+                            rotate_at = Some(Instant::now() + ROTATION_RETRY);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads both sockets for the overlap window, forwarding each message once.
+    /// On success the replacement socket is returned and the old one can be dropped.
+    async fn overlap(
+        &mut self,
+        old: &mut WsRead,
+        mut new: WsRead,
+    ) -> Result<(WsRead, u64), OverlapError> {
+        let mut handover = Handover::default();
+        let mut out = Vec::new();
+        let mut forwarded: u64 = 0;
+        let mut old_open = true;
+
+        let deadline = sleep(self.policy.rotation_overlap);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                _ = &mut deadline => break,
+                item = old.next(), if old_open => match classify(item) {
+                    Frame::Text(text) => handover.on_frame(Source::Old, text, &mut out),
+                    Frame::Ignored => {}
+                    Frame::End(reason) => {
+                        warn!("Old connection ended during overlap: {}", reason);
+                        old_open = false;
+                        handover.close_old(&mut out);
+                    }
+                },
+                item = new.next() => match classify(item) {
+                    Frame::Text(text) => handover.on_frame(Source::Replacement, text, &mut out),
+                    Frame::Ignored => {}
+                    Frame::End(reason) if old_open => return Err(OverlapError::ReplacementFailed(reason)),
+                    Frame::End(reason) => {
+                        return Err(OverlapError::Session(SessionEnd::Disconnected(reason)));
+                    }
+                },
+            }
+
+            for text in out.drain(..) {
+                forwarded += 1;
+                self.on_text(text).map_err(OverlapError::Session)?;
+            }
+        }
+
+        // streams the old socket never caught up on continue from the replacement
+        handover.close_old(&mut out);
+        for text in out.drain(..) {
+            forwarded += 1;
+            self.on_text(text).map_err(OverlapError::Session)?;
+        }
+
+        info!(
+            "Overlap finished: {} messages forwarded, {} duplicates dropped",
+            forwarded, handover.dropped
+        );
+
+        Ok((new, forwarded))
+    }
+
+    /// Bring the replacement socket, opened with `opened_with`, up to date with subscription
+    /// changes acknowledged on the old socket while it was being opened.
+    async fn replay_changes(
+        &mut self,
+        write: &mut WsWrite,
+        opened_with: &[String],
+    ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+        let added: Vec<String> = self
+            .active
+            .iter()
+            .filter(|s| !opened_with.contains(s))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = opened_with
+            .iter()
+            .filter(|s| !self.active.contains(s))
+            .cloned()
+            .collect();
+
+        for (method, params) in [
+            (ControlMethod::Subscribe, added),
+            (ControlMethod::Unsubscribe, removed),
+        ] {
+            if params.is_empty() {
+                continue;
+            }
+
+            info!(
+                "Replaying {} {:?} onto the replacement connection",
+                method.as_str(),
+                params
+            );
+
+            // nobody waits on the reply, the original request was already answered
+            let (reply, _) = oneshot::channel();
+            let cmd = ControlCommand {
+                method,
+                market: Some(self.market),
+                params,
+                reply,
+            };
+            self.send_request(write, cmd).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Market data goes to the dispatcher, request acknowledgements are resolved here.
+    fn on_text(&mut self, text: String) -> Result<(), SessionEnd> {
+        // combined-stream payloads always lead with the stream name
+        if !text.starts_with("{\"stream\"")
+            && let Ok(resp) = serde_json::from_str::<RpcResponse>(&text)
+        {
+            self.on_response(resp);
+            return Ok(());
+        }
+
+        forward(&self.tx, self.market, text)
+    }
+
+    async fn send_request(
+        &mut self,
+        write: &mut WsWrite,
+        cmd: ControlCommand,
+    ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let frame = json!({
+            "method": cmd.method.as_str(),
+            "params": cmd.params,
+            "id": id,
+        });
+
+        debug!("Sending control request {}", frame);
+        write.send(Message::Text(frame.to_string())).await?;
+
+        self.pending.insert(
+            id,
+            PendingRequest {
+                method: cmd.method,
+                params: cmd.params,
+                reply: cmd.reply,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn on_response(&mut self, resp: RpcResponse) {
+        let Some(req) = self.pending.remove(&resp.id) else {
+            warn!("Response for unknown request id {}", resp.id);
+            return;
+        };
+
+        let error = match (resp.error, resp.code, resp.msg) {
+            (Some(e), _, _) => Some(format!("{} (code {})", e.msg, e.code)),
+            (None, Some(code), msg) => Some(format!("{} (code {})", msg.unwrap_or_default(), code)),
+            _ => None,
+        };
+
+        if let Some(e) = error {
+            warn!("{} {:?} rejected: {}", req.method.as_str(), req.params, e);
+            let _ = req.reply.send(Err(e));
+            return;
+        }
+
+        self.apply(req.method, &req.params);
+        info!(
+            "{} {:?} acknowledged (id {})",
+            req.method.as_str(),
+            req.params,
+            resp.id
+        );
+
+        let _ = req.reply.send(Ok(resp.result.unwrap_or(Value::Null)));
+    }
+
+    /// Track the subscription set so reconnects and rotations reopen the same streams.
+    fn apply(&mut self, method: ControlMethod, params: &[String]) {
+        match method {
+            ControlMethod::Subscribe => {
+                for p in params {
+                    if !self.active.contains(p) {
+                        self.active.push(p.clone());
+                    }
+                }
+            }
+            ControlMethod::Unsubscribe => self.active.retain(|s| !params.contains(s)),
+            ControlMethod::ListSubscriptions => {}
+        }
+    }
+
+    fn apply_offline(&mut self, cmd: ControlCommand) {
+        self.apply(cmd.method, &cmd.params);
+
+        let result = match cmd.method {
+            ControlMethod::ListSubscriptions => json!(self.active),
+            _ => Value::Null,
+        };
+
+        info!(
+            "{} {:?} applied while disconnected",
+            cmd.method.as_str(),
+            cmd.params
+        );
+        let _ = cmd.reply.send(Ok(result));
+    }
+
+    fn fail_pending(&mut self, reason: &str) {
+        for (_, req) in self.pending.drain() {
+            let _ = req.reply.send(Err(reason.to_string()));
+        }
+    }
+}
+
+/// Resolves to the next control command, or never if no control channel is attached.
+async fn recv_command(commands: &mut Option<mpsc::Receiver<ControlCommand>>) -> ControlCommand {
+    loop {
+        match commands {
+            Some(rx) => match rx.recv().await {
+                Some(cmd) => return cmd,
+                None => *commands = None,
+            },
+            None => std::future::pending().await,
+        }
+    }
+}
+
+enum OverlapError {
+    Session(SessionEnd),
+    ReplacementFailed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(id: i64) -> String {
+        json!({"stream": "btcusdt@aggTrade", "data": {"e": "aggTrade", "a": id}}).to_string()
+    }
+
+    fn ids(out: &[String]) -> Vec<i64> {
+        out.iter()
+            .map(|raw| Handover::sequence_key(raw).unwrap().1)
+            .collect()
+    }
+
+    fn feed(handover: &mut Handover, frames: &[(Source, i64)]) -> Vec<i64> {
+        let mut out = Vec::new();
+        for &(source, id) in frames {
+            handover.on_frame(source, trade(id), &mut out);
+        }
+        ids(&out)
+    }
+
+    #[test]
+    fn replacement_behind_takes_over_once_caught_up() {
+        use Source::*;
+        let mut handover = Handover::default();
+
+        let out = feed(
+            &mut handover,
+            &[
+                (Old, 1),
+                (Old, 2),
+                (Old, 3),
+                (Replacement, 2),
+                (Replacement, 3),
+            ],
+        );
+        assert_eq!(out, [1, 2, 3]);
+
+        // cut over: the old socket is ignored, the replacement continues
+        let out = feed(&mut handover, &[(Old, 4), (Replacement, 4), (Old, 5)]);
+        assert_eq!(out, [4]);
+    }
+
+    #[test]
+    fn replacement_ahead_is_held_until_old_backlog_drains() {
+        use Source::*;
+        let mut handover = Handover::default();
+
+        let out = feed(
+            &mut handover,
+            &[(Replacement, 5), (Replacement, 6), (Old, 3), (Old, 4)],
+        );
+        assert_eq!(out, [3, 4]);
+
+        let out = feed(&mut handover, &[(Old, 5), (Old, 6), (Replacement, 7)]);
+        assert_eq!(out, [5, 6, 7]);
+    }
+
+    #[test]
+    fn held_frames_flush_in_order_when_old_closes() {
+        use Source::*;
+        let mut handover = Handover::default();
+
+        let out = feed(
+            &mut handover,
+            &[(Old, 1), (Replacement, 4), (Replacement, 5)],
+        );
+        assert_eq!(out, [1]);
+
+        let mut out = Vec::new();
+        handover.close_old(&mut out);
+        assert_eq!(ids(&out), [4, 5]);
+    }
+
+    #[test]
+    fn spot_partial_depth_is_keyed_by_last_update_id() {
+        let raw = json!({
+            "stream": "btcusdt@depth20@100ms",
+            "data": {"lastUpdateId": 42, "bids": [], "asks": []}
+        })
+        .to_string();
+        assert_eq!(
+            Handover::sequence_key(&raw),
+            Some(("btcusdt@depth20@100ms".to_string(), 42))
+        );
+    }
+
+    #[test]
+    fn unkeyed_frames_come_from_the_old_socket() {
+        let mut handover = Handover::default();
+        let mut out = Vec::new();
+
+        handover.on_frame(
+            Source::Replacement,
+            r#"{"result":null,"id":1}"#.into(),
+            &mut out,
+        );
+        handover.on_frame(Source::Old, r#"{"result":null,"id":2}"#.into(), &mut out);
+        assert_eq!(out, [r#"{"result":null,"id":2}"#]);
+
+        handover.close_old(&mut out);
+        handover.on_frame(
+            Source::Replacement,
+            r#"{"result":null,"id":3}"#.into(),
+            &mut out,
+        );
+        assert_eq!(out.len(), 2);
+    }
+}
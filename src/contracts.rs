@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::market::Market;
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Deserialize)]
+struct SymbolInfo {
+    symbol: String,
+    #[serde(rename = "contractSize")]
+    contract_size: i64,
+}
+
+/// COIN-M contract sizes from `/dapi/v1/exchangeInfo`. Quantities on COIN-M are counted in
+/// contracts worth a fixed USD amount each (100 for BTCUSD, 10 for most others).
+pub struct ContractSpecs {
+    client: reqwest::Client,
+    rest_url: Option<String>,
+    sizes: RwLock<HashMap<String, Decimal>>,
+    // symbols already reported missing, each triggers one refresh
+    unknown: Mutex<HashSet<String>>,
+    missed: AtomicBool,
+}
+
+const LOAD_ATTEMPTS: u32 = 5;
+const LOAD_RETRY_INITIAL: Duration = Duration::from_secs(1);
+
+impl ContractSpecs {
+    /// `rest_url` is the COIN-M REST base, `None` when COIN-M isn't subscribed.
+    pub fn new(rest_url: Option<&str>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            rest_url: rest_url.map(|u| u.trim_end_matches('/').to_string()),
+            sizes: RwLock::new(HashMap::new()),
+            unknown: Mutex::new(HashSet::new()),
+            missed: AtomicBool::new(false),
+        }
+    }
+
+    /// `refresh` with backoff, for startup where an empty table leaves every COIN-M row
+    /// without notional until the next refresh.
+    pub async fn load(&self) -> anyhow::Result<()> {
+        let mut delay = LOAD_RETRY_INITIAL;
+        let mut attempt = 1;
+
+        loop {
+            match self.refresh().await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < LOAD_ATTEMPTS => {
+                    warn!(
+                        "Failed to load COIN-M contract sizes (attempt {attempt}/{LOAD_ATTEMPTS}), retrying in {delay:?}: {e:#}"
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let Some(rest_url) = &self.rest_url else {
+            return Ok(());
+        };
+
+        let info = self
+            .client
+            .get(format!("{rest_url}/dapi/v1/exchangeInfo"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ExchangeInfo>()
+            .await?;
+
+        let sizes: HashMap<String, Decimal> = info
+            .symbols
+            .into_iter()
+            .map(|s| (s.symbol, Decimal::from(s.contract_size)))
+            .collect();
+
+        info!("Loaded contract sizes for {} COIN-M symbols", sizes.len());
+        // symbols still unknown after this refresh are reported again
+        self.unknown
+            .lock()
+            .unwrap()
+            .retain(|s| !sizes.contains_key(s));
+        *self.sizes.write().unwrap() = sizes;
+
+        Ok(())
+    }
+
+    /// Contract size for `symbol`, only defined on COIN-M. A symbol missing from the
+    /// table, e.g. a delivery contract listed since the last refresh, is warned about once
+    /// and requests a refresh through `take_refresh_request`.
+    pub fn contract_size(&self, market: Market, symbol: &str) -> Option<Decimal> {
+        if market != Market::CoinM {
+            return None;
+        }
+
+        let size = self.sizes.read().unwrap().get(symbol).copied();
+        if size.is_none() && self.unknown.lock().unwrap().insert(symbol.to_string()) {
+            warn!(
+                "No contract size for COIN-M {symbol}, notional is left empty until a refresh finds it"
+            );
+            self.missed.store(true, Ordering::Relaxed);
+        }
+
+        size
+    }
+
+    /// Whether a lookup missed since the last call
+    pub fn take_refresh_request(&self) -> bool {
+        self.missed.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Base asset quantity and quote notional (USD on COIN-M) for `qty` at `price`, so volumes
+/// compare across markets. Linear markets quote quantity in the base asset already; a COIN-M
+/// contract is worth `contract_size` USD, and an unknown contract size leaves both unset.
+pub fn notional(
+    market: Market,
+    contract_size: Option<Decimal>,
+    price: Decimal,
+    qty: Decimal,
+) -> (Option<Decimal>, Option<Decimal>) {
+    match (market, contract_size) {
+        (Market::CoinM, Some(cs)) => {
+            let usd = qty * cs;
+            let base = usd.checked_div(price).map(|b| b.round_dp(10));
+            (base, Some(usd))
+        }
+        (Market::CoinM, None) => (None, None),
+        (Market::Spot | Market::UsdM, _) => (Some(qty), Some(price * qty)),
+    }
+}
@@ -0,0 +1,191 @@
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, warn};
+
+use crate::market::Market;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlMethod {
+    Subscribe,
+    Unsubscribe,
+    ListSubscriptions,
+}
+
+impl ControlMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Subscribe => "SUBSCRIBE",
+            Self::Unsubscribe => "UNSUBSCRIBE",
+            Self::ListSubscriptions => "LIST_SUBSCRIPTIONS",
+        }
+    }
+}
+
+/// A request for the stream supervisor, answered once Binance acknowledges it
+pub struct ControlCommand {
+    pub method: ControlMethod,
+    // required once more than one market is subscribed
+    pub market: Option<Market>,
+    pub params: Vec<String>,
+    pub reply: oneshot::Sender<Result<Value, String>>,
+}
+
+type Parsed = (ControlMethod, Option<Market>, Vec<String>);
+
+fn parse_command(line: &str) -> Result<Parsed, String> {
+    let mut parts = line.split_whitespace().peekable();
+
+    let method = match parts.next().map(|m| m.to_uppercase()).as_deref() {
+        Some("SUBSCRIBE") => ControlMethod::Subscribe,
+        Some("UNSUBSCRIBE") => ControlMethod::Unsubscribe,
+        Some("LIST_SUBSCRIPTIONS") | Some("LIST") => ControlMethod::ListSubscriptions,
+        Some(other) => return Err(format!("unknown command '{other}'")),
+        None => return Err("empty command".into()),
+    };
+
+    // an optional market comes first, stream names always contain '@'
+    let market = match parts.peek().map(|p| p.parse::<Market>()) {
+        Some(Ok(market)) => {
+            parts.next();
+            Some(market)
+        }
+        _ => None,
+    };
+
+    // stream names are case sensitive past the symbol (aggTrade, bookTicker)
+    let params: Vec<String> = parts.map(str::to_string).collect();
+
+    if method != ControlMethod::ListSubscriptions && params.is_empty() {
+        return Err(format!("{} needs at least one stream", method.as_str()));
+    }
+
+    Ok((method, market, params))
+}
+
+/// Bind the control socket at `path`. A stale socket from a previous run is replaced,
+/// any other file at the path is left alone and reported as an error.
+pub fn bind_control(path: &Path) -> std::io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let listener = UnixListener::bind(path)?;
+    info!("Control socket listening on {}", path.display());
+
+    Ok(listener)
+}
+
+/// Serve line based commands on a bound control socket, e.g.
+/// `SUBSCRIBE ethusdt@aggTrade ethusdt@depth20@100ms`, `UNSUBSCRIBE ...`, `LIST_SUBSCRIPTIONS`.
+/// With several markets the market goes first: `SUBSCRIBE spot ethusdt@aggTrade`.
+/// Each command gets one JSON line back.
+pub async fn serve_control(listener: UnixListener, commands: mpsc::Sender<ControlCommand>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let commands = commands.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_client(stream, commands).await {
+                        warn!("Control client error: {}", e);
+                    }
+                });
+            }
+            Err(e) => error!("Control socket accept failed: {}", e),
+        }
+    }
+}
+
+async fn handle_client(
+    stream: UnixStream,
+    commands: mpsc::Sender<ControlCommand>,
+) -> std::io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match parse_command(&line) {
+            Ok((method, market, params)) => {
+                info!(
+                    "Control command: {} {:?} {:?}",
+                    method.as_str(),
+                    market,
+                    params
+                );
+
+                let (reply_tx, reply_rx) = oneshot::channel();
+                let cmd = ControlCommand {
+                    method,
+                    market,
+                    params,
+                    reply: reply_tx,
+                };
+
+                if commands.send(cmd).await.is_err() {
+                    json!({"ok": false, "error": "stream supervisor stopped"})
+                } else {
+                    match reply_rx.await {
+                        Ok(Ok(result)) => json!({"ok": true, "result": result}),
+                        Ok(Err(e)) => json!({"ok": false, "error": e}),
+                        Err(_) => json!({"ok": false, "error": "request dropped"}),
+                    }
+                }
+            }
+            Err(e) => json!({"ok": false, "error": e}),
+        };
+
+        write.write_all(format!("{response}\n").as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+/// Hand each command to the supervisor of its market. A command without a market is only
+/// accepted while a single market is subscribed.
+pub async fn route_commands(
+    mut commands: mpsc::Receiver<ControlCommand>,
+    routes: HashMap<Market, mpsc::Sender<ControlCommand>>,
+) {
+    while let Some(cmd) = commands.recv().await {
+        let market = match cmd.market {
+            Some(market) => market,
+            None if routes.len() == 1 => *routes.keys().next().unwrap(),
+            None => {
+                let _ = cmd.reply.send(Err(format!(
+                    "several markets are subscribed, name one: {} <market> <streams>",
+                    cmd.method.as_str()
+                )));
+                continue;
+            }
+        };
+
+        match routes.get(&market) {
+            Some(route) => {
+                if let Err(mpsc::error::SendError(cmd)) = route.send(cmd).await {
+                    let _ = cmd.reply.send(Err(format!("{market} supervisor stopped")));
+                }
+            }
+            None => {
+                let _ = cmd
+                    .reply
+                    .send(Err(format!("market {market} is not subscribed")));
+            }
+        }
+    }
+}
@@ -1,8 +1,65 @@
 use crate::types::DepthUpdateData;
 
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+
 use polars::lazy::prelude::*;
 use polars::prelude::*;
 
+use std::fmt;
+use std::str::FromStr;
+
+/// Aggressor side of a hypothetical market order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+impl FromStr for OrderSide {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "buy" | "b" => Ok(Self::Buy),
+            "sell" | "s" => Ok(Self::Sell),
+            _ => Err(format!("unknown side '{s}' (expected buy or sell)")),
+        }
+    }
+}
+
+impl fmt::Display for OrderSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Buy => f.write_str("buy"),
+            Self::Sell => f.write_str("sell"),
+        }
+    }
+}
+
+/// Order size in base asset quantity or quote notional
+#[derive(Debug, Clone, Copy)]
+pub enum OrderSize {
+    Quantity(f64),
+    Notional(f64),
+}
+
+/// Result of walking one side of the book with a hypothetical market order
+#[derive(Debug, Clone)]
+pub struct ImpactEstimate {
+    pub mid: f64,
+    pub avg_price: f64,
+    // price of the last level touched
+    pub worst_price: f64,
+    pub filled_qty: f64,
+    pub filled_notional: f64,
+    // cost vs mid, positive when the fill is worse than mid for either side
+    pub slippage_bps: f64,
+    pub levels_consumed: usize,
+    // false when the visible book ran out before the size was filled
+    pub complete: bool,
+}
+
 pub struct Orderbook {
     df: DataFrame,
 }
@@ -12,22 +69,28 @@ impl Orderbook {
     pub fn from_depth_update(update: &DepthUpdateData) -> PolarsResult<Self> {
         // let depth_update_id = update.e2;
 
+        Self::from_levels(&update.b, &update.a)
+    }
+
+    /// Construct an Orderbook from best-first bid and ask levels.
+    /// Prices and quantities become f64 here, analytics don't need exact decimals.
+    pub fn from_levels(bids: &[[Decimal; 2]], asks: &[[Decimal; 2]]) -> PolarsResult<Self> {
         let mut sides = Vec::new();
         let mut prices = Vec::new();
         let mut quantities = Vec::new();
         let mut level_ids = Vec::new();
 
-        for (i, [price, qty]) in update.b.iter().enumerate() {
+        for (i, [price, qty]) in bids.iter().enumerate() {
             sides.push(1i32);
-            prices.push(*price);
-            quantities.push(*qty);
+            prices.push(price.to_f64().unwrap_or(f64::NAN));
+            quantities.push(qty.to_f64().unwrap_or(f64::NAN));
             level_ids.push(i as i32 + 1);
         }
 
-        for (i, [price, qty]) in update.a.iter().enumerate() {
+        for (i, [price, qty]) in asks.iter().enumerate() {
             sides.push(-1i32);
-            prices.push(*price);
-            quantities.push(*qty);
+            prices.push(price.to_f64().unwrap_or(f64::NAN));
+            quantities.push(qty.to_f64().unwrap_or(f64::NAN));
             level_ids.push(i as i32 + 1);
         }
 
@@ -53,13 +116,7 @@ impl Orderbook {
             lit(0f64).alias("cumulative_depth"),
         ]);
 
-        let orderbook_oos_df = orderbook_oos.clone().collect()?;
-        let mid_price: f64 = orderbook_oos_df
-            .column("price")?
-            .f64()?
-            .sum()
-            .unwrap_or(0.0)
-            / 2.0;
+        let mid_price: f64 = self.mid_price()?.unwrap_or(0.0);
 
         let orderbook_depth = concat(
             &[lf, orderbook_oos],
@@ -74,11 +131,117 @@ impl Orderbook {
             ((col("price") - lit(mid_price)) / lit(mid_price) * lit(10_000f64))
                 .alias("bps_from_mid"),
         ])
-        .sort(vec!["price"], Default::default());
+        // level 0 and level 1 share the best price on each side: order them towards the
+        // spread, bid L1 then L0 and ask L0 then L1, the same as `NativeBook::rows`
+        .sort_by_exprs(
+            [col("price"), col("level_id") * col("side")],
+            SortMultipleOptions::default()
+                .with_order_descending_multi([false, true])
+                .with_maintain_order(true),
+        );
 
         // self.df = orderbook_depth.collect()?;
 
-        Ok(orderbook_depth.collect()?)
+        orderbook_depth.collect()
+    }
+
+    /// Mean of the best bid and ask, `None` when either side is empty
+    pub fn mid_price(&self) -> PolarsResult<Option<f64>> {
+        let best = self
+            .df
+            .clone()
+            .lazy()
+            .filter(col("level_id").eq(lit(1)))
+            .collect()?;
+
+        if best.height() != 2 {
+            return Ok(None);
+        }
+
+        Ok(best.column("price")?.f64()?.sum().map(|p| p / 2.0))
+    }
+
+    /// Walk the asks (buy) or bids (sell) best first until `size` is filled. `None` when
+    /// the book has no mid or the opposite side is empty. Only the levels in this book are
+    /// visible, so a partial depth stream understates impact for large orders.
+    pub fn estimate_impact(
+        &self,
+        side: OrderSide,
+        size: OrderSize,
+    ) -> PolarsResult<Option<ImpactEstimate>> {
+        let Some(mid) = self.mid_price()? else {
+            return Ok(None);
+        };
+
+        let book_side = match side {
+            OrderSide::Buy => -1i32,
+            OrderSide::Sell => 1i32,
+        };
+
+        let levels = self
+            .df
+            .clone()
+            .lazy()
+            .filter(col("side").eq(lit(book_side)))
+            .sort(vec!["level_id"], Default::default())
+            .collect()?;
+
+        let prices = levels.column("price")?.f64()?;
+        let quantities = levels.column("quantity")?.f64()?;
+
+        let mut filled_qty = 0.0;
+        let mut filled_notional = 0.0;
+        let mut worst_price = f64::NAN;
+        let mut levels_consumed = 0;
+        let mut complete = false;
+
+        // a zero quantity removes the level, it offers nothing to fill against
+        for (price, qty) in prices
+            .into_no_null_iter()
+            .zip(quantities.into_no_null_iter())
+            .filter(|(_, qty)| *qty > 0.0)
+        {
+            let remaining = match size {
+                OrderSize::Quantity(q) => q - filled_qty,
+                OrderSize::Notional(n) => (n - filled_notional) / price,
+            };
+            if remaining <= 0.0 {
+                complete = true;
+                break;
+            }
+
+            let take = qty.min(remaining);
+            filled_qty += take;
+            filled_notional += take * price;
+            worst_price = price;
+            levels_consumed += 1;
+
+            if take >= remaining {
+                complete = true;
+                break;
+            }
+        }
+
+        if levels_consumed == 0 {
+            return Ok(None);
+        }
+
+        let avg_price = filled_notional / filled_qty;
+        let slippage_bps = match side {
+            OrderSide::Buy => (avg_price - mid) / mid * 10_000.0,
+            OrderSide::Sell => (mid - avg_price) / mid * 10_000.0,
+        };
+
+        Ok(Some(ImpactEstimate {
+            mid,
+            avg_price,
+            worst_price,
+            filled_qty,
+            filled_notional,
+            slippage_bps,
+            levels_consumed,
+            complete,
+        }))
     }
 
     #[allow(dead_code)]
@@ -86,3 +249,115 @@ impl Orderbook {
         &self.df
     }
 }
+
+/// One row of the depth profile, the same columns `Orderbook::calculate_depth` produces
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DepthRow {
+    pub side: i32,
+    pub price: f64,
+    pub quantity: f64,
+    // 0 marks the zero quantity row at each best price that anchors the profile at mid
+    pub level_id: i32,
+    pub cumulative_depth: f64,
+    pub bps_from_mid: f64,
+}
+
+/// Plain vector counterpart of `Orderbook` for the per-update path. Builds the same depth
+/// profile as `calculate_depth` in one pass over the levels without a DataFrame; the Polars
+/// version stays for batch research. The row buffer is reused across `rebuild` calls.
+#[derive(Default)]
+pub struct NativeBook {
+    rows: Vec<DepthRow>,
+    mid: Option<f64>,
+}
+
+impl NativeBook {
+    /// Construct from best-first bid and ask levels
+    pub fn from_levels(
+        bids: impl IntoIterator<Item = [Decimal; 2]>,
+        asks: impl IntoIterator<Item = [Decimal; 2]>,
+    ) -> Self {
+        let mut book = Self::default();
+        book.rebuild(bids, asks);
+        book
+    }
+
+    pub fn rebuild(
+        &mut self,
+        bids: impl IntoIterator<Item = [Decimal; 2]>,
+        asks: impl IntoIterator<Item = [Decimal; 2]>,
+    ) {
+        let to_f64 = |d: Decimal| d.to_f64().unwrap_or(f64::NAN);
+
+        let mut bids = bids.into_iter().peekable();
+        let mut asks = asks.into_iter().peekable();
+        let best_bid = bids.peek().map(|l| to_f64(l[0]));
+        let best_ask = asks.peek().map(|l| to_f64(l[0]));
+
+        self.mid = best_bid.zip(best_ask).map(|(b, a)| (b + a) / 2.0);
+        // same fallback as calculate_depth for a one sided book
+        let mid = self.mid.unwrap_or(0.0);
+        let bps = |price: f64| (price - mid) / mid * 10_000.0;
+
+        self.rows.clear();
+
+        // bids are accumulated best first and then flipped so the profile ascends in price
+        if let Some(price) = best_bid {
+            self.rows.push(DepthRow {
+                side: 1,
+                price,
+                bps_from_mid: bps(price),
+                ..Default::default()
+            });
+        }
+
+        let mut cumulative = 0.0;
+        for (i, [price, qty]) in bids.enumerate() {
+            let (price, quantity) = (to_f64(price), to_f64(qty));
+            cumulative += quantity;
+            self.rows.push(DepthRow {
+                side: 1,
+                price,
+                quantity,
+                level_id: i as i32 + 1,
+                cumulative_depth: cumulative,
+                bps_from_mid: bps(price),
+            });
+        }
+        self.rows.reverse();
+
+        if let Some(price) = best_ask {
+            self.rows.push(DepthRow {
+                side: -1,
+                price,
+                bps_from_mid: bps(price),
+                ..Default::default()
+            });
+        }
+
+        let mut cumulative = 0.0;
+        for (i, [price, qty]) in asks.enumerate() {
+            let (price, quantity) = (to_f64(price), to_f64(qty));
+            cumulative += quantity;
+            self.rows.push(DepthRow {
+                side: -1,
+                price,
+                quantity,
+                level_id: i as i32 + 1,
+                cumulative_depth: cumulative,
+                bps_from_mid: bps(price),
+            });
+        }
+    }
+
+    /// Depth profile in ascending price order: deepest bid to best bid, then best ask to
+    /// deepest ask, with each side's level 0 row next to the spread
+    pub fn rows(&self) -> &[DepthRow] {
+        &self.rows
+    }
+
+    /// Mean of the best bid and ask, `None` when either side is empty
+    pub fn mid_price(&self) -> Option<f64> {
+        self.mid
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::handler::{HandlerContext, NativeBooks, message_handler};
+use crate::market::{Inbound, Market};
+use crate::types::stream_name;
+
+fn shard_for(key: (Market, &str), shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
+/// Route inbound messages to a fixed pool of workers keyed by market and stream name.
+/// Messages for one stream are always handled by the same worker, in arrival order,
+/// while different streams are processed in parallel.
+pub fn spawn_dispatcher(
+    mut rx: mpsc::Receiver<Inbound>,
+    ctx: Arc<HandlerContext>,
+    shards: usize,
+    shard_capacity: usize,
+) {
+    let shards = shards.max(1);
+    let mut senders = Vec::with_capacity(shards);
+
+    for shard in 0..shards {
+        let (tx, mut shard_rx) = mpsc::channel::<Inbound>(shard_capacity);
+        senders.push(tx);
+
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let mut native_books = NativeBooks::new();
+            while let Some(msg) = shard_rx.recv().await {
+                if let Err(err) = message_handler(&ctx, &mut native_books, &msg).await {
+                    error!("Message handling error (shard {}): {}", shard, err);
+                }
+            }
+            info!("Shard {} exiting", shard);
+        });
+    }
+
+    info!("Dispatcher started with {} shards", shards);
+
+    tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            let shard = shard_for((msg.market, stream_name(&msg.raw)), shards);
+
+            // awaiting here applies backpressure to the inbound queue instead of reordering
+            if senders[shard].send(msg).await.is_err() {
+                error!("Shard {} closed, shutting dispatcher", shard);
+                break;
+            }
+        }
+        info!("Dispatcher exiting (rx closed)");
+    });
+}
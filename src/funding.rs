@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rust_decimal::Decimal;
+use tracing::warn;
+
+use crate::types::MarkPriceUpdateData;
+
+// the last update before settlement must be this recent to stand for the settled values,
+// markPrice publishes every 1s or 3s
+const MAX_SETTLEMENT_LAG_MS: i64 = 5_000;
+
+/// A settled funding interval, derived from the mark price stream
+pub struct FundingEvent {
+    pub symbol: String,
+    pub funding_time: i64,
+    pub funding_rate: Decimal,
+    pub mark_price: Decimal,
+    // not published on COIN-M
+    pub index_price: Option<Decimal>,
+}
+
+/// Watches `T` (next funding time) per symbol. When it rolls forward, funding settled at the
+/// previous `T` with the last rate published before it. If that update is older than
+/// `MAX_SETTLEMENT_LAG_MS` (the stream was down across the settlement) its values are stale
+/// and no event is emitted.
+#[derive(Default)]
+pub struct FundingTracker {
+    last: Mutex<HashMap<String, MarkPriceUpdateData>>,
+}
+
+impl FundingTracker {
+    pub fn observe(&self, update: &MarkPriceUpdateData) -> Option<FundingEvent> {
+        let mut last = self.last.lock().unwrap();
+
+        let prev = last.insert(update.s.clone(), update.clone())?;
+
+        if update.t <= prev.t || prev.t == 0 {
+            return None;
+        }
+
+        // delivery contracts have no funding rate
+        let funding_rate = prev.r?;
+
+        if prev.t - prev.e2 > MAX_SETTLEMENT_LAG_MS {
+            warn!(
+                "Skipping {} funding settlement at {}: last update was {} ms before it",
+                prev.s,
+                prev.t,
+                prev.t - prev.e2
+            );
+            return None;
+        }
+
+        Some(FundingEvent {
+            symbol: prev.s,
+            funding_time: prev.t,
+            funding_rate,
+            mark_price: prev.p,
+            index_price: prev.i,
+        })
+    }
+}
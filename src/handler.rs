@@ -1,73 +1,285 @@
-use crate::data_manip::Orderbook;
+use crate::bars::BarAggregator;
+use crate::basis::BasisMonitor;
+use crate::batch_writer::BatchWriter;
+use crate::book_metrics::BookMetricsEngine;
+use crate::contracts::ContractSpecs;
+use crate::data_manip::NativeBook;
 use crate::db_controller::Database;
-use crate::types::{AggTrade, BookTickerUpdate, Cli, DepthUpdate, MarkPriceUpdate};
+use crate::funding::FundingTracker;
+use crate::local_book::{BookManager, is_diff_depth_stream};
+use crate::market::{Inbound, Market};
+use crate::sequence::SequenceTracker;
+use crate::types::{Cli, MarketEvent, decode_message};
 use crate::utils::i64_to_ts;
-use serde_json::Value;
+use rust_decimal::prelude::ToPrimitive;
+use std::collections::HashMap;
+use std::sync::Arc;
 
 use chrono::Utc;
-use tracing::{debug, trace};
+use tracing::{debug, info, trace, warn};
 
-pub async fn message_handler(db: &Database, cli: &Cli, raw: &str) -> anyhow::Result<()> {
-    let ws_msg: Value = serde_json::from_str(raw)?;
-    let msg_type = ws_msg["data"]["e"].as_str().unwrap_or("");
+/// Shared state handed to every message handler invocation
+pub struct HandlerContext {
+    pub db: Database,
+    pub cli: Arc<Cli>,
+    // one per subscribed market, snapshots come from that market's REST api
+    pub books: HashMap<Market, Arc<BookManager>>,
+    pub sequences: SequenceTracker,
+    pub writer: Option<BatchWriter>,
+    pub funding: FundingTracker,
+    pub bars: Option<BarAggregator>,
+    pub contracts: ContractSpecs,
+    pub basis: Option<BasisMonitor>,
+    pub metrics: Option<BookMetricsEngine>,
+}
+
+/// Last depth profile per symbol, rebuilt in place on each update. Each dispatcher shard
+/// owns its own, so depth analytics never contend across shards.
+pub type NativeBooks = HashMap<(Market, String), NativeBook>;
 
-    match msg_type {
-        "aggTrade" => {
-            let agg_trade: AggTrade = serde_json::from_str(raw)?;
+pub async fn message_handler(
+    ctx: &HandlerContext,
+    native_books: &mut NativeBooks,
+    msg: &Inbound,
+) -> anyhow::Result<()> {
+    let db = &ctx.db;
+    let cli = &*ctx.cli;
+    let market = msg.market;
 
-            db.insert_trade(&agg_trade.data).await?;
+    let envelope = decode_message(market, &msg.raw)?;
+    let stream = envelope.stream;
+    let msg_type = envelope.data.name();
 
-            let user_dt = i64_to_ts(agg_trade.data.t, &cli.tz).format("%Y-%m-%d %H:%M:%S%.3f");
-            let num_trades = agg_trade.data.l - agg_trade.data.f + 1;
+    match envelope.data {
+        MarketEvent::AggTrade(agg_trade) => {
+            let user_dt = i64_to_ts(agg_trade.t, &cli.tz).format("%Y-%m-%d %H:%M:%S%.3f");
+            let num_trades = agg_trade.l - agg_trade.f + 1;
 
             trace!(
                 "Msg: {}, Ts: {}, Price: {}, Quantity: {}, Maker: {}, # Trades {}",
-                msg_type, user_dt, agg_trade.data.p, agg_trade.data.q, agg_trade.data.m, num_trades
+                msg_type, user_dt, agg_trade.p, agg_trade.q, agg_trade.m, num_trades
             );
+
+            // bars are aggregated before the trade is handed off, stored after it
+            let bars = ctx
+                .bars
+                .as_ref()
+                .map(|bars| bars.on_trade(market, &agg_trade));
+            let contract_size = ctx.contracts.contract_size(market, &agg_trade.s);
+
+            let stored = match &ctx.writer {
+                Some(writer) => writer.write_trade(market, contract_size, agg_trade).await,
+                None => db
+                    .insert_trade(market, contract_size, &agg_trade)
+                    .await
+                    .map_err(Into::into),
+            };
+
+            // bars are derived data: a failed write is logged, and bars the aggregator has
+            // already closed are stored even if the trade itself failed
+            if let Some(bars) = bars
+                && let Err(e) = db.insert_bars(&bars).await
+            {
+                warn!("Failed to store {} bars for {market}: {e}", bars.len());
+            }
+
+            stored?;
         }
-        "depthUpdate" => {
-            let depth_update: DepthUpdate = serde_json::from_str(raw)?;
+        MarketEvent::DepthUpdate(depth_update) => {
+            // partial depth snapshots skip ids by design, only diff streams are continuous
+            let diff_stream = is_diff_depth_stream(&stream);
+            let gap_before = diff_stream && ctx.sequences.check(market, &stream, &depth_update);
+
+            let symbol = depth_update.s.clone();
+            let (event_time, last_update_id) = (depth_update.e2, depth_update.u2);
+
+            // per update analytics use the native book, Orderbook/Polars is for batch research.
+            // Only partial depth snapshots and the synced local book are whole books. Each
+            // symbol keeps one book that is rebuilt in place, reusing its row buffer
+            let analytics = {
+                let ob = native_books.entry((market, symbol.clone())).or_default();
+
+                let rebuilt = match ctx.books.get(&market) {
+                    Some(books) if cli.full_book && diff_stream => {
+                        if gap_before {
+                            books.resync(&symbol);
+                        }
 
-            db.insert_book_update(&depth_update.data).await?;
+                        // diff events only make sense applied to the full local book
+                        books
+                            .on_diff(&depth_update, |book| ob.rebuild(book.bids(), book.asks()))
+                            .is_some()
+                    }
+                    // a raw diff only holds the levels that changed, it isn't a book
+                    _ if diff_stream => false,
+                    _ => {
+                        ob.rebuild(
+                            depth_update.b.iter().copied(),
+                            depth_update.a.iter().copied(),
+                        );
+                        true
+                    }
+                };
 
-            let mut ob = Orderbook::from_depth_update(&depth_update.data)?;
-            let _depth = ob.calculate_depth()?;
+                rebuilt.then(|| {
+                    let metrics = ctx.metrics.as_ref().and_then(|engine| {
+                        engine.compute(market, &symbol, event_time, last_update_id, ob.rows())
+                    });
+                    (metrics, ob.mid_price())
+                })
+            };
 
-            let dt = i64_to_ts(depth_update.data.e2, &cli.tz).format("%Y-%m-%d %H:%M:%S%.3f");
+            let dt = i64_to_ts(depth_update.e2, &cli.tz).format("%Y-%m-%d %H:%M:%S%.3f");
 
             debug!(
-                "Depth update @ {} for {} complete @ {}",
-                depth_update.data.s,
+                "Depth update @ {} {} for {} complete @ {}",
+                market,
+                depth_update.s,
                 dt,
                 Utc::now()
             );
             // trace!("{:#?}", &depth);
-        }
-        "bookTicker" => {
-            let book_update: BookTickerUpdate = serde_json::from_str(raw)?;
 
-            let dt = i64_to_ts(book_update.data.e2, &cli.tz).format("%Y-%m-%d %H:%M:%S%.3f");
-            let a = book_update.data.a;
-            let b = book_update.data.b;
+            let contract_size = ctx.contracts.contract_size(market, &symbol);
+
+            // the raw update is stored before any analytics can fail
+            match &ctx.writer {
+                Some(writer) => {
+                    writer
+                        .write_book_update(
+                            market,
+                            contract_size,
+                            depth_update,
+                            gap_before,
+                            !diff_stream,
+                        )
+                        .await?
+                }
+                None => {
+                    db.insert_book_update(
+                        market,
+                        contract_size,
+                        &depth_update,
+                        gap_before,
+                        !diff_stream,
+                    )
+                    .await?
+                }
+            }
+
+            if let Some((metrics, mid)) = analytics {
+                // metrics are derived data, a failed write is logged rather than failing the
+                // update that was already stored
+                if let Some(engine) = &ctx.metrics
+                    && let Some(metrics) = metrics
+                {
+                    let (levels, bands_bps) = (engine.levels(), engine.bands_bps());
+                    let stored = match &ctx.writer {
+                        Some(writer) => writer.write_book_metrics(levels, bands_bps, metrics).await,
+                        None => db
+                            .insert_book_metrics(levels, bands_bps, &metrics)
+                            .await
+                            .map_err(Into::into),
+                    };
+
+                    if let Err(e) = stored {
+                        warn!("Failed to store book metrics for {market} {symbol}: {e:#}");
+                    }
+                }
+
+                if let Some(basis) = &ctx.basis
+                    && let Some(mid) = mid
+                {
+                    basis.on_mid(market, &symbol, Utc::now().timestamp_millis(), mid);
+                }
+            }
+        }
+        MarketEvent::BookTicker(book_update) => {
+            let dt = i64_to_ts(book_update.e2, &cli.tz).format("%Y-%m-%d %H:%M:%S%.3f");
+            let a = book_update.a.to_f64().unwrap_or(f64::NAN);
+            let b = book_update.b.to_f64().unwrap_or(f64::NAN);
             let ba_spread = ((a - b) / a) * 10_000.0;
             let mid_price = (a + b) / 2.0;
 
             trace!(
                 "Msg: {}, Ts: {}, Bid: {:.8}, Ask: {:.8}, Spread: {:.8}, Mid: {:.8}, Quote Size [{}, {}]",
-                msg_type, dt, b, a, ba_spread, mid_price, book_update.data.bq, book_update.data.aq
+                msg_type, dt, b, a, ba_spread, mid_price, book_update.bq, book_update.aq
             );
+
+            if let Some(basis) = &ctx.basis {
+                basis.on_mid(
+                    market,
+                    &book_update.s,
+                    Utc::now().timestamp_millis(),
+                    mid_price,
+                );
+            }
+
+            match &ctx.writer {
+                Some(writer) => writer.write_book_ticker(market, book_update).await?,
+                None => db.insert_book_ticker(market, &book_update).await?,
+            }
         }
-        "markPriceUpdate" => {
-            let mark_price: MarkPriceUpdate = serde_json::from_str(raw)?;
+        MarketEvent::MarkPriceUpdate(mark_price) => {
+            db.insert_mark_price(market, &mark_price).await?;
 
-            let dt = i64_to_ts(mark_price.data.e2, &cli.tz).format("%Y-%m-%d %H:%M:%S");
+            if let Some(basis) = &ctx.basis {
+                let mark = mark_price.p.to_f64().unwrap_or(f64::NAN);
+                basis.on_mark(market, &mark_price.s, Utc::now().timestamp_millis(), mark);
+            }
+
+            if let Some(event) = ctx.funding.observe(&mark_price) {
+                db.insert_funding_event(market, &event).await?;
+
+                info!(
+                    "Funding settled for {} @ {}: rate {}",
+                    event.symbol,
+                    i64_to_ts(event.funding_time, &cli.tz).format("%Y-%m-%d %H:%M:%S"),
+                    event.funding_rate
+                );
+            }
+
+            let dt = i64_to_ts(mark_price.e2, &cli.tz).format("%Y-%m-%d %H:%M:%S");
+
+            trace!(
+                "Msg: {}, Timestamp: {}, Symbol: {}, Price: {}, Rate: {:?}",
+                msg_type, dt, mark_price.s, mark_price.p, mark_price.r
+            );
+        }
+        MarketEvent::ForceOrder(liquidation) => {
+            db.insert_liquidation(market, &liquidation).await?;
+
+            let o = &liquidation.o;
+            let dt = i64_to_ts(o.t, &cli.tz).format("%Y-%m-%d %H:%M:%S%.3f");
+
+            debug!(
+                "Liquidation @ {} for {}: {} {} @ {} (avg {}, {})",
+                dt, o.s, o.side, o.z, o.p, o.ap, o.status
+            );
+        }
+        MarketEvent::Kline(kline) => {
+            if kline.k.x || cli.store_open_klines {
+                db.upsert_kline(market, &kline.s, None, kline.e2, &kline.k)
+                    .await?;
+            }
+
+            trace!(
+                "Msg: {}, Symbol: {}, Interval: {}, Close: {}, Volume: {}, Closed: {}",
+                msg_type, kline.s, kline.k.i, kline.k.c, kline.k.v, kline.k.x
+            );
+        }
+        MarketEvent::ContinuousKline(kline) => {
+            if kline.k.x || cli.store_open_klines {
+                let symbol = format!("{}_{}", kline.ps, kline.ct);
+                db.upsert_kline(market, &symbol, Some(&kline.ct), kline.e2, &kline.k)
+                    .await?;
+            }
 
             trace!(
-                "Msg: {}, Timestamp: {}, Symbol: {}, Price: {}, Rate: {}",
-                msg_type, dt, mark_price.data.s, mark_price.data.p, mark_price.data.r
+                "Msg: {}, Pair: {}, Contract: {}, Interval: {}, Close: {}, Closed: {}",
+                msg_type, kline.ps, kline.ct, kline.k.i, kline.k.c, kline.k.x
             );
         }
-        _ => {}
     }
 
     Ok(())
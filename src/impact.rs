@@ -0,0 +1,79 @@
+use std::io::Write;
+use std::pin::pin;
+
+use futures::TryStreamExt;
+
+use chrono::Utc;
+use tracing::{info, warn};
+
+use crate::data_manip::{OrderSize, Orderbook};
+use crate::db_controller::Database;
+use crate::types::ImpactArgs;
+use crate::utils::i64_to_ts;
+
+/// Replays the impact question over every stored partial depth snapshot (`depth5`,
+/// `depth20`, ...) in the window and writes one CSV row per snapshot to stdout. Diff depth
+/// rows only hold the levels that changed and are skipped, as are rows stored before the
+/// two were told apart unless `--include-legacy` is given. Quantities are in the base
+/// asset on every market, COIN-M contracts included.
+pub async fn run_historical(db: &Database, args: &ImpactArgs, tz: &str) -> anyhow::Result<()> {
+    let size = match (args.qty, args.notional) {
+        (Some(q), _) => OrderSize::Quantity(q),
+        (None, Some(n)) => OrderSize::Notional(n),
+        (None, None) => anyhow::bail!("one of --qty or --notional is required"),
+    };
+
+    let symbol = args.symbol.to_uppercase();
+    let to = args.to.unwrap_or_else(Utc::now);
+
+    info!(
+        "Evaluating {} {} {:?} over {} depth snapshots from {} to {}",
+        args.side, symbol, size, args.market, args.from, to
+    );
+
+    let mut out = std::io::stdout().lock();
+    writeln!(
+        out,
+        "event_time,mid,avg_price,worst_price,filled_qty,filled_notional,slippage_bps,levels_consumed,complete"
+    )?;
+
+    let mut snapshots =
+        pin!(db.book_snapshots(args.market, &symbol, args.from, to, args.include_legacy,));
+    let (mut evaluated, mut partial) = (0usize, 0usize);
+
+    while let Some(snapshot) = snapshots.try_next().await? {
+        evaluated += 1;
+
+        let ob = Orderbook::from_levels(&snapshot.bids, &snapshot.asks)?;
+        let Some(est) = ob.estimate_impact(args.side, size)? else {
+            continue;
+        };
+
+        if !est.complete {
+            partial += 1;
+        }
+
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{:.4},{},{}",
+            i64_to_ts(snapshot.event_time, tz).format("%Y-%m-%d %H:%M:%S%.3f"),
+            est.mid,
+            est.avg_price,
+            est.worst_price,
+            est.filled_qty,
+            est.filled_notional,
+            est.slippage_bps,
+            est.levels_consumed,
+            est.complete
+        )?;
+    }
+
+    if evaluated == 0 {
+        warn!("No partial depth snapshots stored for {symbol} in the window");
+    }
+    if partial > 0 {
+        warn!("Visible depth was too thin to fill the order in {partial} snapshots");
+    }
+
+    Ok(())
+}
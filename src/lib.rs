@@ -0,0 +1,22 @@
+pub mod bars;
+pub mod basis;
+pub mod batch_writer;
+pub mod book_metrics;
+pub mod config;
+pub mod connection;
+pub mod contracts;
+pub mod control;
+pub mod data_manip;
+pub mod db_controller;
+pub mod dispatch;
+pub mod funding;
+pub mod handler;
+pub mod impact;
+pub mod local_book;
+pub mod market;
+pub mod migrations;
+pub mod pg_copy;
+pub mod sequence;
+pub mod streams;
+pub mod types;
+pub mod utils;
@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rust_decimal::Decimal;
+use tracing::{debug, error, info, warn};
+
+use crate::market::Market;
+use crate::types::{DepthSnapshot, DepthUpdateData};
+
+// don't hammer the REST endpoint if snapshots keep failing
+const SNAPSHOT_RETRY: Duration = Duration::from_secs(5);
+// diff events kept while waiting for a snapshot
+const MAX_BUFFERED: usize = 10_000;
+
+/// Full depth for one symbol, seeded from a REST snapshot and kept current from diff events
+pub struct LocalBook {
+    bids: BTreeMap<Decimal, Decimal>,
+    asks: BTreeMap<Decimal, Decimal>,
+    last_update_id: i64,
+    // false until the first diff event bridging the snapshot has been applied
+    synced: bool,
+}
+
+impl LocalBook {
+    fn from_snapshot(snapshot: &DepthSnapshot) -> Self {
+        let mut book = Self {
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            last_update_id: snapshot.last_update_id,
+            synced: false,
+        };
+
+        Self::apply_levels(&mut book.bids, &snapshot.bids);
+        Self::apply_levels(&mut book.asks, &snapshot.asks);
+
+        book
+    }
+
+    fn apply_levels(side: &mut BTreeMap<Decimal, Decimal>, levels: &[[Decimal; 2]]) {
+        for [price, qty] in levels {
+            if qty.is_zero() {
+                side.remove(price);
+            } else {
+                side.insert(*price, *qty);
+            }
+        }
+    }
+
+    fn apply(&mut self, update: &DepthUpdateData) {
+        Self::apply_levels(&mut self.bids, &update.b);
+        Self::apply_levels(&mut self.asks, &update.a);
+        self.last_update_id = update.u2;
+    }
+
+    /// Bids best first
+    pub fn bids(&self) -> impl Iterator<Item = [Decimal; 2]> + '_ {
+        self.bids.iter().rev().map(|(p, q)| [*p, *q])
+    }
+
+    /// Asks best first
+    pub fn asks(&self) -> impl Iterator<Item = [Decimal; 2]> + '_ {
+        self.asks.iter().map(|(p, q)| [*p, *q])
+    }
+
+    #[allow(dead_code)]
+    pub fn last_update_id(&self) -> i64 {
+        self.last_update_id
+    }
+}
+
+enum Step {
+    Applied,
+    Stale,
+    Resync(String),
+}
+
+#[derive(Default)]
+struct SymbolBook {
+    book: Option<LocalBook>,
+    buffer: VecDeque<DepthUpdateData>,
+    fetching: bool,
+    last_fetch: Option<Instant>,
+}
+
+impl SymbolBook {
+    /// Binance futures procedure: drop events with `u` < `lastUpdateId`, the first applied event
+    /// must satisfy `U` <= `lastUpdateId` <= `u`, and every later `pu` must equal the previous `u`.
+    /// Spot is off by one: drop `u` <= `lastUpdateId`, the first event must cover
+    /// `lastUpdateId` + 1 and every later `U` must be the previous `u` + 1.
+    fn step(&mut self, market: Market, update: &DepthUpdateData) -> Step {
+        let Some(book) = self.book.as_mut() else {
+            return Step::Resync("no snapshot".into());
+        };
+
+        let last = book.last_update_id;
+
+        if book.synced {
+            match update.p {
+                Some(pu) if pu != last => {
+                    return Step::Resync(format!("pu {pu} != last u {last}"));
+                }
+                None if update.u != last + 1 => {
+                    return Step::Resync(format!("U {} != last u {} + 1", update.u, last));
+                }
+                _ => {}
+            }
+        } else {
+            let bridge = if market.is_futures() { last } else { last + 1 };
+
+            if update.u2 < bridge {
+                return Step::Stale;
+            } else if update.u > bridge {
+                return Step::Resync(format!(
+                    "snapshot {} older than first event U {}",
+                    last, update.u
+                ));
+            }
+        }
+
+        book.apply(update);
+        book.synced = true;
+        Step::Applied
+    }
+
+    fn reset(&mut self) {
+        self.book = None;
+        self.buffer.clear();
+    }
+
+    /// Install `snapshot` and replay the buffered diff events over it, returning how many
+    /// were replayed. A snapshot that can't be bridged drops the book again so the next
+    /// diff event requests another one.
+    fn seed(&mut self, market: Market, snapshot: &DepthSnapshot) -> Result<usize, String> {
+        self.book = Some(LocalBook::from_snapshot(snapshot));
+
+        let buffered = std::mem::take(&mut self.buffer);
+        for update in &buffered {
+            if let Step::Resync(reason) = self.step(market, update) {
+                self.reset();
+                return Err(reason);
+            }
+        }
+
+        Ok(buffered.len())
+    }
+}
+
+/// Per-symbol full order books for one market, fed from the diff depth stream
+pub struct BookManager {
+    market: Market,
+    client: reqwest::Client,
+    rest_url: String,
+    limit: u32,
+    books: Mutex<HashMap<String, SymbolBook>>,
+}
+
+impl BookManager {
+    pub fn new(market: Market, rest_url: &str, limit: u32) -> Self {
+        Self {
+            market,
+            client: reqwest::Client::new(),
+            rest_url: rest_url.trim_end_matches('/').to_string(),
+            limit,
+            books: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Apply a diff event. Runs `f` over the synced book, or returns `None` while the
+    /// symbol is still waiting on a snapshot.
+    pub fn on_diff<R>(
+        self: &Arc<Self>,
+        update: &DepthUpdateData,
+        f: impl FnOnce(&LocalBook) -> R,
+    ) -> Option<R> {
+        let mut books = self.books.lock().unwrap();
+        let entry = books.entry(update.s.clone()).or_default();
+
+        if entry.book.is_some() {
+            match entry.step(self.market, update) {
+                Step::Applied => return entry.book.as_ref().map(f),
+                Step::Stale => return None,
+                Step::Resync(reason) => {
+                    warn!(
+                        "{} {} book out of sync ({}), resyncing",
+                        self.market, update.s, reason
+                    );
+                    entry.reset();
+                }
+            }
+        }
+
+        if entry.buffer.len() >= MAX_BUFFERED {
+            entry.buffer.pop_front();
+        }
+        entry.buffer.push_back(update.clone());
+
+        let retry_due = entry
+            .last_fetch
+            .is_none_or(|t| t.elapsed() >= SNAPSHOT_RETRY);
+
+        if !entry.fetching && retry_due {
+            entry.fetching = true;
+            entry.last_fetch = Some(Instant::now());
+
+            let manager = Arc::clone(self);
+            let symbol = update.s.clone();
+            tokio::spawn(async move { manager.sync(symbol).await });
+        }
+
+        None
+    }
+
+    /// Drop the book for `symbol` so the next diff event triggers a fresh snapshot.
+    pub fn resync(&self, symbol: &str) {
+        if let Some(entry) = self.books.lock().unwrap().get_mut(symbol) {
+            entry.reset();
+        }
+    }
+
+    async fn fetch_snapshot(&self, symbol: &str) -> anyhow::Result<DepthSnapshot> {
+        let url = format!("{}{}", self.rest_url, self.market.depth_path());
+
+        let snapshot = self
+            .client
+            .get(&url)
+            .query(&[
+                ("symbol", symbol.to_uppercase()),
+                ("limit", self.limit.to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<DepthSnapshot>()
+            .await?;
+
+        Ok(snapshot)
+    }
+
+    async fn sync(&self, symbol: String) {
+        debug!("Fetching depth snapshot for {}", symbol);
+        let snapshot = self.fetch_snapshot(&symbol).await;
+
+        let mut books = self.books.lock().unwrap();
+        let entry = books.entry(symbol.clone()).or_default();
+        entry.fetching = false;
+
+        let snapshot = match snapshot {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Depth snapshot for {} failed: {}", symbol, e);
+                return;
+            }
+        };
+
+        match entry.seed(self.market, &snapshot) {
+            Ok(replayed) => info!(
+                "{} book seeded from snapshot {} ({} buffered events replayed)",
+                symbol, snapshot.last_update_id, replayed
+            ),
+            // the next diff event will request another snapshot
+            Err(reason) => warn!(
+                "{} snapshot could not be bridged ({}), retrying",
+                symbol, reason
+            ),
+        }
+    }
+}
+
+/// Diff depth streams are `<sym>@depth` / `<sym>@depth@100ms`, as opposed to `<sym>@depth20@100ms`
+pub fn is_diff_depth_stream(stream: &str) -> bool {
+    stream.split('@').nth(1).is_some_and(|kind| kind == "depth")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(v: i64) -> Decimal {
+        Decimal::from(v)
+    }
+
+    fn snapshot(last_update_id: i64) -> DepthSnapshot {
+        DepthSnapshot {
+            last_update_id,
+            e2: None,
+            t: None,
+            bids: vec![[dec(99), dec(1)], [dec(98), dec(2)]],
+            asks: vec![[dec(101), dec(1)]],
+        }
+    }
+
+    /// Diff event covering ids `first..=last`, `pu` only on futures
+    fn update(first: i64, last: i64, pu: Option<i64>) -> DepthUpdateData {
+        DepthUpdateData {
+            s: "BTCUSDT".into(),
+            u: first,
+            u2: last,
+            p: pu,
+            b: vec![[dec(99), dec(0)]],
+            a: vec![[dec(102), dec(3)]],
+            ..Default::default()
+        }
+    }
+
+    fn buffered(updates: Vec<DepthUpdateData>) -> SymbolBook {
+        SymbolBook {
+            buffer: updates.into(),
+            ..Default::default()
+        }
+    }
+
+    fn last_update_id(entry: &SymbolBook) -> i64 {
+        entry.book.as_ref().unwrap().last_update_id()
+    }
+
+    #[test]
+    fn futures_snapshot_bridges_on_u_and_continues_on_pu() {
+        let mut entry = buffered(vec![
+            update(90, 95, Some(89)),
+            update(96, 103, Some(95)),
+            update(104, 110, Some(103)),
+        ]);
+
+        assert_eq!(entry.seed(Market::UsdM, &snapshot(100)), Ok(3));
+        assert_eq!(last_update_id(&entry), 110);
+
+        let book = entry.book.as_ref().unwrap();
+        assert_eq!(book.bids().collect::<Vec<_>>(), [[dec(98), dec(2)]]);
+        assert_eq!(book.asks().count(), 2);
+    }
+
+    #[test]
+    fn futures_bridge_may_start_at_last_update_id() {
+        let mut entry = buffered(vec![update(100, 105, Some(99))]);
+
+        assert_eq!(entry.seed(Market::UsdM, &snapshot(100)), Ok(1));
+        assert_eq!(last_update_id(&entry), 105);
+    }
+
+    #[test]
+    fn spot_snapshot_bridges_on_the_next_id() {
+        // u == lastUpdateId is already in the snapshot on spot
+        let mut entry = buffered(vec![update(95, 100, None), update(101, 104, None)]);
+
+        assert_eq!(entry.seed(Market::Spot, &snapshot(100)), Ok(2));
+        assert_eq!(last_update_id(&entry), 104);
+
+        assert!(matches!(
+            entry.step(Market::Spot, &update(105, 107, None)),
+            Step::Applied
+        ));
+        assert_eq!(last_update_id(&entry), 107);
+    }
+
+    #[test]
+    fn spot_first_event_past_the_next_id_needs_a_new_snapshot() {
+        let mut entry = buffered(vec![update(102, 104, None)]);
+
+        assert!(entry.seed(Market::Spot, &snapshot(100)).is_err());
+        assert!(entry.book.is_none());
+    }
+
+    #[test]
+    fn stale_snapshot_is_dropped() {
+        let mut entry = buffered(vec![update(120, 125, Some(119))]);
+
+        assert!(entry.seed(Market::UsdM, &snapshot(100)).is_err());
+        assert!(entry.book.is_none());
+        assert!(entry.buffer.is_empty());
+    }
+
+    #[test]
+    fn snapshot_newer_than_buffer_waits_for_live_events() {
+        let mut entry = buffered(vec![update(90, 95, Some(89))]);
+
+        assert_eq!(entry.seed(Market::UsdM, &snapshot(100)), Ok(1));
+        assert!(!entry.book.as_ref().unwrap().synced);
+
+        assert!(matches!(
+            entry.step(Market::UsdM, &update(96, 99, Some(95))),
+            Step::Stale
+        ));
+        assert!(matches!(
+            entry.step(Market::UsdM, &update(100, 104, Some(99))),
+            Step::Applied
+        ));
+    }
+
+    #[test]
+    fn futures_pu_gap_triggers_resync() {
+        let mut entry = buffered(vec![update(98, 103, Some(97))]);
+        entry.seed(Market::UsdM, &snapshot(100)).unwrap();
+
+        assert!(matches!(
+            entry.step(Market::UsdM, &update(105, 110, Some(104))),
+            Step::Resync(_)
+        ));
+    }
+
+    #[test]
+    fn spot_id_gap_triggers_resync() {
+        let mut entry = buffered(vec![update(101, 103, None)]);
+        entry.seed(Market::Spot, &snapshot(100)).unwrap();
+
+        assert!(matches!(
+            entry.step(Market::Spot, &update(105, 110, None)),
+            Step::Resync(_)
+        ));
+    }
+
+    #[test]
+    fn events_without_a_snapshot_resync() {
+        let mut entry = SymbolBook::default();
+
+        assert!(matches!(
+            entry.step(Market::UsdM, &update(1, 2, Some(0))),
+            Step::Resync(_)
+        ));
+    }
+}
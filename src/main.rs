@@ -1,25 +1,32 @@
-mod db_controller;
-mod handler;
-mod data_manip;
-mod types;
-mod utils;
-
-use crate::db_controller::{Database, del_database};
-use crate::handler::message_handler;
-use crate::types::Cli;
-use crate::utils::init_tracing;
+use rust_binance_pricing::bars::BarAggregator;
+use rust_binance_pricing::basis::BasisMonitor;
+use rust_binance_pricing::batch_writer::BatchWriter;
+use rust_binance_pricing::book_metrics::BookMetricsEngine;
+use rust_binance_pricing::config::{DbConfig, FileConfig};
+use rust_binance_pricing::connection::{ReconnectPolicy, StreamSupervisor};
+use rust_binance_pricing::contracts::ContractSpecs;
+use rust_binance_pricing::control::{bind_control, route_commands, serve_control};
+use rust_binance_pricing::db_controller::{Database, del_database};
+use rust_binance_pricing::dispatch::spawn_dispatcher;
+use rust_binance_pricing::funding::FundingTracker;
+use rust_binance_pricing::handler::HandlerContext;
+use rust_binance_pricing::impact::run_historical;
+use rust_binance_pricing::local_book::BookManager;
+use rust_binance_pricing::market::{Inbound, Market};
+use rust_binance_pricing::sequence::SequenceTracker;
+use rust_binance_pricing::streams::stream_names;
+use rust_binance_pricing::types::{Cli, Command};
+use rust_binance_pricing::utils::init_tracing;
 
 use clap::Parser;
-use futures::StreamExt;
-use tokio::sync::{Semaphore, mpsc};
+use tokio::sync::mpsc;
 use tokio::time::{Duration, interval};
 use tracing::Level;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
-use tracing::{error, info};
-
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{info, warn};
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() {
@@ -35,37 +42,158 @@ async fn main() {
 
     let cli = Arc::new(cli);
 
+    let file_config = match &cli.config {
+        Some(path) => FileConfig::load(path).expect("Failed to load config file"),
+        None => FileConfig::default(),
+    };
+    let db_config = DbConfig::resolve(&cli.db, &file_config).expect("Invalid database config");
+
     if cli.del_db {
-        del_database("test_crypto_pricing")
-            .await
-            .expect("Failed to delete DB");
+        del_database(&db_config).await.expect("Failed to delete DB");
     }
 
-    let db = Database::connect("test_crypto_pricing")
+    let db = Database::connect(&db_config)
         .await
         .expect("Failed to connect DB");
 
-    db.create_tables("sql/create_tables.sql")
-        .await
-        .expect("Failed to create tables");
+    db.migrate().await.expect("Failed to migrate schema");
+
+    if let Some(Command::Impact(args)) = &cli.command {
+        run_historical(&db, args, &cli.tz)
+            .await
+            .expect("Failed to evaluate historical impact");
+        return;
+    }
 
     info!("Market data client is starting...");
 
-    let stream_path = cli
-        .sym
-        .iter()
-        .map(|s| format!("{s}@depth20@100ms"))
-        .collect::<Vec<_>>()
-        .join("/");
+    let mut markets = Vec::new();
+    for m in &cli.market {
+        if !markets.contains(m) {
+            markets.push(*m);
+        }
+    }
+
+    let contracts = ContractSpecs::new(
+        markets
+            .contains(&Market::CoinM)
+            .then(|| cli.rest_url(Market::CoinM)),
+    );
 
-    let url = format!("wss://fstream.binance.com/stream?streams={}", stream_path);
+    if let Err(e) = contracts.load().await {
+        warn!("Failed to load COIN-M contract sizes: {e:#}");
+    }
+
+    // shared state for the dispatcher and its workers
+    let ctx = Arc::new(HandlerContext {
+        db: db.clone(),
+        cli: cli.clone(),
+        books: markets
+            .iter()
+            .map(|m| {
+                let books = BookManager::new(*m, cli.rest_url(*m), cli.snapshot_limit);
+                (*m, Arc::new(books))
+            })
+            .collect(),
+        sequences: SequenceTracker::default(),
+        writer: cli.copy_ingest.then(|| {
+            BatchWriter::spawn(
+                db.clone(),
+                cli.batch_size,
+                Duration::from_millis(cli.flush_ms),
+            )
+        }),
+        funding: FundingTracker::default(),
+        bars: (!cli.bars.is_empty()).then(|| BarAggregator::new(cli.bars.clone(), 1_024)),
+        contracts,
+        basis: cli.basis.then(|| BasisMonitor::new(cli.basis_max_age_ms)),
+        metrics: cli.book_metrics.then(|| {
+            BookMetricsEngine::new(
+                cli.imbalance_levels.clone(),
+                cli.depth_bands_bps.clone(),
+                1_024,
+            )
+        }),
+    });
 
-    info!("Connecting to: {}", url);
-    let (ws_stream, _) = connect_async(&url).await.expect("WebSocket connect failed");
-    let (_write, mut read) = ws_stream.split();
+    if cli.basis && !(markets.contains(&Market::Spot) && markets.contains(&Market::UsdM)) {
+        warn!("--basis needs both spot and usdm in --market, no basis will be recorded");
+    }
+
+    if ctx.basis.is_some() {
+        let ctx_for_basis = ctx.clone();
+        let every = cli.basis_interval_ms.max(1);
+
+        tokio::spawn(async move {
+            let Some(basis) = &ctx_for_basis.basis else {
+                return;
+            };
+            let mut ticker = interval(Duration::from_millis(every));
+            loop {
+                ticker.tick().await;
+
+                // snap to the clock so rows line up across symbols and restarts
+                let now = chrono::Utc::now().timestamp_millis();
+                let samples = basis.sample(now - now % every as i64);
+
+                if let Err(e) = ctx_for_basis.db.insert_basis(&samples).await {
+                    warn!("Failed to store basis samples: {e}");
+                }
+            }
+        });
+    }
+
+    // contract sizes rarely change, but new delivery contracts get listed: refresh hourly,
+    // and within 30s of a symbol missing from the table
+    let ctx_for_contracts = ctx.clone();
+
+    tokio::spawn(async move {
+        let contracts = &ctx_for_contracts.contracts;
+        let mut ticker = interval(Duration::from_secs(30));
+        let hourly = Duration::from_secs(3600);
+        let mut due = Instant::now() + hourly;
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if !contracts.take_refresh_request() && Instant::now() < due {
+                continue;
+            }
+
+            // a failed refresh is retried on the next tick
+            match contracts.refresh().await {
+                Ok(()) => due = Instant::now() + hourly,
+                Err(e) => {
+                    warn!("Failed to refresh COIN-M contract sizes: {e:#}");
+                    due = Instant::now();
+                }
+            }
+        }
+    });
+
+    // time bars of symbols that stopped trading still close once their bucket has passed
+    if ctx.bars.is_some() {
+        let ctx_for_bars = ctx.clone();
+
+        tokio::spawn(async move {
+            let Some(bars) = &ctx_for_bars.bars else {
+                return;
+            };
+            let mut ticker = interval(Duration::from_secs(1));
+            loop {
+                ticker.tick().await;
+
+                let now = chrono::Utc::now().timestamp_millis();
+                let closed = bars.close_expired(now, ctx_for_bars.cli.bar_close_grace_ms);
+
+                if let Err(e) = ctx_for_bars.db.insert_bars(&closed).await {
+                    warn!("Failed to store {} closed bars: {e}", closed.len());
+                }
+            }
+        });
+    }
 
-    info!("Connected to Binance WebSocket.");
     let start = Instant::now();
+    let ctx_for_heartbeat = ctx.clone();
 
     tokio::spawn(async move {
         let mut ticker = interval(Duration::from_secs(60));
@@ -79,69 +207,109 @@ async fn main() {
             let sec = secs % 60;
 
             info!(
-                "Heartbeat: system running (uptime: {}h {}m {}s)",
-                hrs, mins, sec
+                "Heartbeat: system running (uptime: {}h {}m {}s, sequence gaps: {}, late trades: {})",
+                hrs,
+                mins,
+                sec,
+                ctx_for_heartbeat.sequences.total_gaps(),
+                ctx_for_heartbeat
+                    .bars
+                    .as_ref()
+                    .map_or(0, BarAggregator::late_trades)
             );
+
+            for s in ctx_for_heartbeat
+                .basis
+                .as_ref()
+                .map(BasisMonitor::summaries)
+                .unwrap_or_default()
+            {
+                info!(
+                    "Basis {}: last {:.2} bps, mean {:.2} bps, std {:.2} bps, range [{:.2}, {:.2}] over {} samples",
+                    s.symbol,
+                    s.last_bps,
+                    s.stats.mean(),
+                    s.stats.std_dev(),
+                    s.stats.min(),
+                    s.stats.max(),
+                    s.stats.count()
+                );
+            }
         }
     });
 
     // bounded channel to avoid unbounded backlog
-    let (tx, mut rx) = mpsc::channel::<String>(10_000);
+    let (tx, rx) = mpsc::channel::<Inbound>(10_000);
 
-    // concurrency limit for DB writes (adjust to your DB capacity)
-    let concurrency_limit = 8usize;
-    let sem = Arc::new(Semaphore::new(concurrency_limit));
+    // one ordered worker per shard, keyed by stream (adjust shards to your DB capacity)
+    spawn_dispatcher(rx, ctx.clone(), cli.shards, 1_000);
 
-    // clone handles for dispatcher
-    let db_for_dispatch = db.clone();
-    let cli_for_dispatch = cli.clone();
-    let sem_for_dispatch = sem.clone();
+    let policy = ReconnectPolicy {
+        initial_delay: Duration::from_millis(cli.reconnect_initial_ms),
+        max_delay: Duration::from_secs(cli.reconnect_max_secs),
+        idle_timeout: Duration::from_secs(cli.idle_timeout_secs),
+        rotate_after: (cli.rotate_after_secs > 0)
+            .then(|| Duration::from_secs(cli.rotate_after_secs)),
+        rotation_overlap: Duration::from_secs(cli.rotation_overlap_secs),
+    };
 
-    // dispatcher: receives messages from queue and spawns worker tasks (bounded by semaphore)
-    tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            let permit = match sem_for_dispatch.clone().acquire_owned().await {
-                Ok(p) => p,
-                Err(_) => {
-                    error!("Semaphore closed, shutting dispatcher");
-                    break;
-                }
-            };
-            let db_worker = db_for_dispatch.clone();
-            let cli_worker = cli_for_dispatch.clone();
-            // spawn a task to process this message; permit held until task ends
-            tokio::spawn(async move {
-                // keep the permit in scope so it is released on drop
-                let _permit = permit;
-                if let Err(err) = message_handler(&db_worker, &*cli_worker, &msg).await {
-                    error!("Message handling error (worker): {}", err);
-                }
-            });
+    // one command channel per market, the control socket routes on the market named
+    let mut commands = HashMap::new();
+    if let Some(path) = cli.control_socket.clone() {
+        let (cmd_tx, cmd_rx) = mpsc::channel(64);
+        let mut routes = HashMap::new();
+        for market in &markets {
+            let (market_tx, market_rx) = mpsc::channel(64);
+            routes.insert(*market, market_tx);
+            commands.insert(*market, market_rx);
         }
-        info!("Dispatcher exiting (rx closed)");
-    });
+        let listener = bind_control(&path).expect("Failed to bind control socket");
+        tokio::spawn(serve_control(listener, cmd_tx));
+        tokio::spawn(route_commands(cmd_rx, routes));
+    }
+
+    // one supervised reader per market: each reconnects on its own, the dispatcher above
+    // survives outages
+    let mut readers = Vec::with_capacity(markets.len());
+
+    for market in markets {
+        let streams = stream_names(&cli, market);
+        info!(
+            "Subscribing to {} {} streams: {}",
+            streams.len(),
+            market,
+            streams.join(", ")
+        );
 
-    while let Some(msg) = read.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                match tx.try_send(text) {
-                    Ok(_) => {}
-                    Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
-                        // queue is full: drop the message and log
-                        error!("Inbound queue full — dropping websocket message");
-                    }
-                    Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
-                        error!("Inbound queue closed — stopping reader");
-                        break;
-                    }
+        let supervisor = StreamSupervisor::new(
+            market,
+            streams,
+            tx.clone(),
+            commands.remove(&market),
+            policy.clone(),
+        );
+        readers.push(tokio::spawn(supervisor.run()));
+    }
+
+    drop(tx);
+
+    tokio::select! {
+        _ = futures::future::join_all(readers) => {}
+        _ = tokio::signal::ctrl_c() => {
+            info!("Shutting down");
+
+            // time bars whose bucket has already ended would otherwise never be stored
+            if let Some(bars) = &ctx.bars {
+                let closed = bars.close_expired(chrono::Utc::now().timestamp_millis(), 0);
+                if let Err(e) = ctx.db.insert_bars(&closed).await {
+                    warn!("Failed to store {} closed bars: {e}", closed.len());
                 }
             }
-            Ok(Message::Close(_)) => {
-                info!("Connection closed.");
-                break;
-            }
-            Ok(Message::Ping(_) | Message::Pong(_) | Message::Binary(_) | Message::Frame(_)) => {}
-            Err(e) => error!("WebSocket error: {}", e),
         }
     }
+
+    // rows still batched in memory are written before exiting
+    if let Some(writer) = &ctx.writer {
+        writer.close().await;
+    }
 }
@@ -0,0 +1,98 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Binance venue a connection belongs to. Symbols are only unique within a market
+/// (BTCUSDT exists on both spot and USD-M), so every stored row carries one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Market {
+    Spot,
+    UsdM,
+    CoinM,
+}
+
+impl Market {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Spot => "spot",
+            Self::UsdM => "usdm",
+            Self::CoinM => "coinm",
+        }
+    }
+
+    pub fn ws_url(&self) -> &'static str {
+        match self {
+            Self::Spot => "wss://stream.binance.com:9443",
+            Self::UsdM => "wss://fstream.binance.com",
+            Self::CoinM => "wss://dstream.binance.com",
+        }
+    }
+
+    pub fn rest_url(&self) -> &'static str {
+        match self {
+            Self::Spot => "https://api.binance.com",
+            Self::UsdM => "https://fapi.binance.com",
+            Self::CoinM => "https://dapi.binance.com",
+        }
+    }
+
+    pub fn depth_path(&self) -> &'static str {
+        match self {
+            Self::Spot => "/api/v3/depth",
+            Self::UsdM => "/fapi/v1/depth",
+            Self::CoinM => "/dapi/v1/depth",
+        }
+    }
+
+    pub fn is_futures(&self) -> bool {
+        !matches!(self, Self::Spot)
+    }
+}
+
+impl FromStr for Market {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spot" => Ok(Self::Spot),
+            "usdm" | "usd-m" | "futures" => Ok(Self::UsdM),
+            "coinm" | "coin-m" => Ok(Self::CoinM),
+            _ => Err(format!(
+                "unknown market '{s}' (expected spot, usdm or coinm)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Market {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `--rest-url` override for one market, e.g. `spot=http://localhost:8080`
+#[derive(Debug, Clone)]
+pub struct RestOverride {
+    pub market: Market,
+    pub url: String,
+}
+
+impl FromStr for RestOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (market, url) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <market>=<url>, got '{s}'"))?;
+
+        Ok(Self {
+            market: market.trim().parse()?,
+            url: url.trim().trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+/// A raw websocket frame and the market it was received from
+pub struct Inbound {
+    pub market: Market,
+    pub raw: String,
+}
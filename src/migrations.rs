@@ -0,0 +1,148 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::info;
+
+// arbitrary key so concurrent instances don't migrate the same database at once
+const MIGRATION_LOCK_KEY: i64 = 0x0062_696e_616e_6365;
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+/// Embedded schema history, applied in order. Never edit a migration once it has shipped,
+/// add a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "orderbook_gap_flag",
+        sql: include_str!("../migrations/0002_orderbook_gap_flag.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "book_ticker",
+        sql: include_str!("../migrations/0003_book_ticker.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "mark_price_funding",
+        sql: include_str!("../migrations/0004_mark_price_funding.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "market_trade_ids",
+        sql: include_str!("../migrations/0005_market_trade_ids.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "liquidations",
+        sql: include_str!("../migrations/0006_liquidations.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "klines",
+        sql: include_str!("../migrations/0007_klines.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "bars",
+        sql: include_str!("../migrations/0008_bars.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "market_column",
+        sql: include_str!("../migrations/0009_market_column.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "contract_notional",
+        sql: include_str!("../migrations/0010_contract_notional.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "basis",
+        sql: include_str!("../migrations/0011_basis.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "orderbook_metrics",
+        sql: include_str!("../migrations/0012_orderbook_metrics.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "basis_annualisation",
+        sql: include_str!("../migrations/0013_basis_annualisation.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "orderbook_partial_flag",
+        sql: include_str!("../migrations/0014_orderbook_partial_flag.sql"),
+    },
+];
+
+/// Apply every migration newer than the database's recorded version. Each migration runs
+/// in its own transaction through the simple query protocol, so multi-statement files with
+/// functions or DO blocks are executed as written.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *conn)
+        .await?;
+
+    let result = async {
+        sqlx::raw_sql(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version BIGINT PRIMARY KEY,
+                name VARCHAR NOT NULL,
+                applied_at timestamptz NOT NULL DEFAULT now()
+            )",
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        let applied: Vec<i64> =
+            sqlx::query_scalar("SELECT version FROM schema_migrations ORDER BY version")
+                .fetch_all(&mut *conn)
+                .await?;
+
+        let mut count = 0;
+
+        for m in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+            let mut tx: Transaction<'_, Postgres> = sqlx::Connection::begin(&mut *conn).await?;
+
+            sqlx::raw_sql(m.sql).execute(&mut *tx).await?;
+
+            sqlx::query("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)")
+                .bind(m.version)
+                .bind(m.name)
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+            count += 1;
+
+            info!("Applied migration {:04}_{}", m.version, m.name);
+        }
+
+        if count == 0 {
+            info!("Schema up to date ({} migrations)", applied.len());
+        }
+
+        Ok::<(), sqlx::Error>(())
+    }
+    .await;
+
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(MIGRATION_LOCK_KEY)
+        .execute(&mut *conn)
+        .await?;
+
+    result
+}
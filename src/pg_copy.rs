@@ -0,0 +1,341 @@
+use rust_decimal::Decimal;
+
+// microseconds between the unix epoch and the postgres epoch (2000-01-01)
+const PG_EPOCH_OFFSET_US: i64 = 946_684_800_000_000;
+
+const INT4_OID: i32 = 23;
+const FLOAT8_OID: i32 = 701;
+
+const NUMERIC_POS: u16 = 0x0000;
+const NUMERIC_NEG: u16 = 0x4000;
+
+/// Builds a `COPY ... FROM STDIN (FORMAT binary)` payload row by row
+pub struct CopyEncoder {
+    buf: Vec<u8>,
+    rows: usize,
+}
+
+impl CopyEncoder {
+    pub fn new() -> Self {
+        let mut buf = Vec::with_capacity(64 * 1024);
+        buf.extend_from_slice(b"PGCOPY\n\xff\r\n\0");
+        buf.extend_from_slice(&0i32.to_be_bytes()); // flags
+        buf.extend_from_slice(&0i32.to_be_bytes()); // header extension length
+        Self { buf, rows: 0 }
+    }
+
+    pub fn row(&mut self, fields: i16) -> &mut Self {
+        self.buf.extend_from_slice(&fields.to_be_bytes());
+        self.rows += 1;
+        self
+    }
+
+    fn field(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf
+            .extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn i16(&mut self, v: i16) -> &mut Self {
+        self.field(&v.to_be_bytes())
+    }
+
+    pub fn i32(&mut self, v: i32) -> &mut Self {
+        self.field(&v.to_be_bytes())
+    }
+
+    pub fn i64(&mut self, v: i64) -> &mut Self {
+        self.field(&v.to_be_bytes())
+    }
+
+    pub fn f64(&mut self, v: f64) -> &mut Self {
+        self.field(&v.to_be_bytes())
+    }
+
+    pub fn i32_array(&mut self, v: &[i32]) -> &mut Self {
+        self.array(INT4_OID, v.iter().map(|x| x.to_be_bytes()))
+    }
+
+    pub fn f64_array(&mut self, v: &[f64]) -> &mut Self {
+        self.array(FLOAT8_OID, v.iter().map(|x| x.to_be_bytes()))
+    }
+
+    /// One dimensional array without nulls: dimension header, then each element as a
+    /// length prefixed value. An empty array has no dimensions.
+    fn array<const N: usize>(
+        &mut self,
+        oid: i32,
+        elems: impl ExactSizeIterator<Item = [u8; N]>,
+    ) -> &mut Self {
+        let len = elems.len();
+        let mut bytes = Vec::with_capacity(20 + len * (4 + N));
+
+        let ndim = i32::from(len > 0);
+        for header in [ndim, 0, oid] {
+            bytes.extend_from_slice(&header.to_be_bytes());
+        }
+        if len > 0 {
+            bytes.extend_from_slice(&(len as i32).to_be_bytes());
+            bytes.extend_from_slice(&1i32.to_be_bytes()); // lower bound
+        }
+
+        for elem in elems {
+            bytes.extend_from_slice(&(N as i32).to_be_bytes());
+            bytes.extend_from_slice(&elem);
+        }
+
+        self.field(&bytes)
+    }
+
+    pub fn null(&mut self) -> &mut Self {
+        self.buf.extend_from_slice(&(-1i32).to_be_bytes());
+        self
+    }
+
+    pub fn opt_i64(&mut self, v: Option<i64>) -> &mut Self {
+        match v {
+            Some(v) => self.i64(v),
+            None => self.null(),
+        }
+    }
+
+    pub fn bool(&mut self, v: bool) -> &mut Self {
+        self.field(&[v as u8])
+    }
+
+    pub fn text(&mut self, v: &str) -> &mut Self {
+        self.field(v.as_bytes())
+    }
+
+    /// `timestamptz` from a millisecond unix timestamp
+    pub fn timestamp_ms(&mut self, ts_ms: i64) -> &mut Self {
+        self.i64(ts_ms * 1_000 - PG_EPOCH_OFFSET_US)
+    }
+
+    pub fn opt_timestamp_ms(&mut self, ts_ms: Option<i64>) -> &mut Self {
+        match ts_ms {
+            Some(ts_ms) => self.timestamp_ms(ts_ms),
+            None => self.null(),
+        }
+    }
+
+    /// `numeric` from a plain decimal string such as `"-123.045"`
+    pub fn numeric(&mut self, v: &str) -> &mut Self {
+        let encoded = encode_numeric(v);
+        self.field(&encoded)
+    }
+
+    pub fn decimal(&mut self, v: &Decimal) -> &mut Self {
+        self.numeric(&v.to_string())
+    }
+
+    pub fn opt_decimal(&mut self, v: Option<&Decimal>) -> &mut Self {
+        match v {
+            Some(v) => self.decimal(v),
+            None => self.null(),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.buf.extend_from_slice(&(-1i16).to_be_bytes());
+        self.buf
+    }
+}
+
+impl Default for CopyEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Postgres numeric wire format: base 10000 digits with a weight (exponent of the first
+/// digit group), a sign word and the display scale.
+fn encode_numeric(v: &str) -> Vec<u8> {
+    let (negative, unsigned) = match v.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, v),
+    };
+
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let int_part = int_part.trim_start_matches('0');
+    let dscale = frac_part.len() as u16;
+
+    // left pad the integer part and right pad the fraction to whole 4 digit groups
+    let int_pad = (4 - int_part.len() % 4) % 4;
+    let frac_pad = (4 - frac_part.len() % 4) % 4;
+
+    let mut padded = String::with_capacity(int_part.len() + frac_part.len() + 6);
+    padded.extend(std::iter::repeat_n('0', int_pad));
+    padded.push_str(int_part);
+    padded.push_str(frac_part);
+    padded.extend(std::iter::repeat_n('0', frac_pad));
+
+    let mut digits: Vec<i16> = padded
+        .as_bytes()
+        .chunks(4)
+        .map(|c| c.iter().fold(0i16, |acc, d| acc * 10 + (d - b'0') as i16))
+        .collect();
+
+    let mut weight = ((int_part.len() + int_pad) / 4) as i16 - 1;
+
+    let leading = digits.iter().take_while(|d| **d == 0).count();
+    digits.drain(..leading);
+    weight -= leading as i16;
+
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+
+    if digits.is_empty() {
+        weight = 0;
+    }
+
+    let sign = if negative && !digits.is_empty() {
+        NUMERIC_NEG
+    } else {
+        NUMERIC_POS
+    };
+
+    let mut out = Vec::with_capacity(8 + digits.len() * 2);
+    out.extend_from_slice(&(digits.len() as i16).to_be_bytes());
+    out.extend_from_slice(&weight.to_be_bytes());
+    out.extend_from_slice(&sign.to_be_bytes());
+    out.extend_from_slice(&dscale.to_be_bytes());
+    for d in digits {
+        out.extend_from_slice(&d.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expected numeric payload: digit count, weight, sign, display scale, base 10000 digits
+    fn numeric(weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(digits.len() as i16).to_be_bytes());
+        out.extend_from_slice(&weight.to_be_bytes());
+        out.extend_from_slice(&sign.to_be_bytes());
+        out.extend_from_slice(&dscale.to_be_bytes());
+        for d in digits {
+            out.extend_from_slice(&d.to_be_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn zero_has_no_digits_and_keeps_its_scale() {
+        assert_eq!(encode_numeric("0"), numeric(0, NUMERIC_POS, 0, &[]));
+        assert_eq!(encode_numeric("0.00"), numeric(0, NUMERIC_POS, 2, &[]));
+        // postgres has no negative zero
+        assert_eq!(encode_numeric("-0.000"), numeric(0, NUMERIC_POS, 3, &[]));
+    }
+
+    #[test]
+    fn sign() {
+        assert_eq!(encode_numeric("42"), numeric(0, NUMERIC_POS, 0, &[42]));
+        assert_eq!(encode_numeric("-42"), numeric(0, NUMERIC_NEG, 0, &[42]));
+        assert_eq!(encode_numeric("-0.5"), numeric(-1, NUMERIC_NEG, 1, &[5000]));
+    }
+
+    #[test]
+    fn digit_groups_and_scale() {
+        assert_eq!(
+            encode_numeric("12345.678"),
+            numeric(1, NUMERIC_POS, 3, &[1, 2345, 6780])
+        );
+        assert_eq!(encode_numeric("0.0001"), numeric(-1, NUMERIC_POS, 4, &[1]));
+        // leading zero groups of the fraction move into the weight
+        assert_eq!(
+            encode_numeric("0.00001"),
+            numeric(-2, NUMERIC_POS, 5, &[1000])
+        );
+    }
+
+    #[test]
+    fn trailing_zeros_are_trimmed_from_digits_not_scale() {
+        assert_eq!(
+            encode_numeric("1.5000"),
+            numeric(0, NUMERIC_POS, 4, &[1, 5000])
+        );
+        assert_eq!(
+            encode_numeric("100000000"),
+            numeric(2, NUMERIC_POS, 0, &[1])
+        );
+        assert_eq!(
+            encode_numeric("000120.10"),
+            numeric(0, NUMERIC_POS, 2, &[120, 1000])
+        );
+    }
+
+    #[test]
+    fn large_values_fill_numeric_30_10() {
+        assert_eq!(
+            encode_numeric("99999999999999999999.9999999999"),
+            numeric(
+                4,
+                NUMERIC_POS,
+                10,
+                &[9999, 9999, 9999, 9999, 9999, 9999, 9999, 9900]
+            )
+        );
+        assert_eq!(
+            encode_numeric(&Decimal::MAX.to_string()),
+            numeric(
+                7,
+                NUMERIC_POS,
+                0,
+                &[7, 9228, 1625, 1426, 4337, 5935, 4395, 335]
+            )
+        );
+    }
+
+    /// The field written for one value, without the row header and length prefix
+    fn field(write: impl FnOnce(&mut CopyEncoder)) -> Vec<u8> {
+        let mut enc = CopyEncoder::new();
+        let header = enc.buf.len();
+        write(&mut enc);
+        enc.buf[header + 4..].to_vec()
+    }
+
+    #[test]
+    fn arrays_are_one_dimensional_with_a_lower_bound_of_one() {
+        let mut expected = Vec::new();
+        for word in [1i32, 0, INT4_OID, 2, 1, 4, 1, 4, 5] {
+            expected.extend_from_slice(&word.to_be_bytes());
+        }
+        assert_eq!(
+            field(|enc| {
+                enc.i32_array(&[1, 5]);
+            }),
+            expected
+        );
+
+        let payload = field(|enc| {
+            enc.f64_array(&[2.5]);
+        });
+        assert_eq!(&payload[8..12], FLOAT8_OID.to_be_bytes());
+        assert_eq!(&payload[20..24], 8i32.to_be_bytes());
+        assert_eq!(&payload[24..], 2.5f64.to_be_bytes());
+    }
+
+    #[test]
+    fn empty_arrays_have_no_dimensions() {
+        let mut expected = Vec::new();
+        for word in [0i32, 0, FLOAT8_OID] {
+            expected.extend_from_slice(&word.to_be_bytes());
+        }
+        assert_eq!(
+            field(|enc| {
+                enc.f64_array(&[]);
+            }),
+            expected
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tracing::warn;
+
+use crate::market::Market;
+use crate::types::DepthUpdateData;
+
+#[derive(Default)]
+struct StreamSequence {
+    last_update_id: Option<i64>,
+    gaps: u64,
+}
+
+/// Per-stream continuity check for diff depth events: each event's `pu` must equal
+/// the `u` of the event before it, otherwise updates were lost in between. Spot events
+/// have no `pu` and must instead start at the previous `u` + 1.
+#[derive(Default)]
+pub struct SequenceTracker {
+    streams: Mutex<HashMap<(Market, String), StreamSequence>>,
+}
+
+impl SequenceTracker {
+    /// Record `update` and report whether a gap precedes it.
+    pub fn check(&self, market: Market, stream: &str, update: &DepthUpdateData) -> bool {
+        let mut streams = self.streams.lock().unwrap();
+        let seq = streams.entry((market, stream.to_string())).or_default();
+
+        let continuous = |last: i64| match update.p {
+            Some(pu) => pu == last,
+            None => update.u == last + 1,
+        };
+
+        let gap = match seq.last_update_id {
+            Some(last) if !continuous(last) => {
+                seq.gaps += 1;
+
+                // futures ids aren't contiguous, only spot ids count the updates lost
+                let missing = match update.p {
+                    Some(_) => String::new(),
+                    None => format!("{} missing ids, ", update.u - last - 1),
+                };
+
+                warn!(
+                    "Sequence gap on {} {}: U {} / pu {:?} after previous u {} ({}{} gaps so far)",
+                    market, stream, update.u, update.p, last, missing, seq.gaps
+                );
+                true
+            }
+            _ => false,
+        };
+
+        seq.last_update_id = Some(update.u2);
+        gap
+    }
+
+    pub fn total_gaps(&self) -> u64 {
+        self.streams.lock().unwrap().values().map(|s| s.gaps).sum()
+    }
+}
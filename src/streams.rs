@@ -0,0 +1,371 @@
+use std::fmt;
+use std::str::FromStr;
+
+use tracing::warn;
+
+use crate::market::Market;
+use crate::types::Cli;
+
+/// A single Binance stream kind, rendered as the suffix after `<symbol>@`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamKind {
+    AggTrade,
+    BookTicker,
+    ForceOrder,
+    MarkPrice {
+        every_second: bool,
+    },
+    // speed_ms is DEFAULT_SPEED for the unsuffixed stream
+    PartialDepth {
+        levels: u8,
+        speed_ms: u16,
+    },
+    DiffDepth {
+        speed_ms: u16,
+    },
+    Kline {
+        interval: &'static str,
+    },
+    // subscribed as `<pair>_<contract>@continuousKline_<interval>`
+    ContinuousKline {
+        contract: &'static str,
+        interval: &'static str,
+    },
+}
+
+// unsuffixed depth streams, 250ms on futures and 1000ms on spot
+const DEFAULT_SPEED: u16 = 0;
+
+const KLINE_INTERVALS: &[&str] = &[
+    "1s", "1m", "3m", "5m", "15m", "30m", "1h", "2h", "4h", "6h", "8h", "12h", "1d", "3d", "1w",
+    "1M",
+];
+
+const CONTRACT_TYPES: &[&str] = &["perpetual", "current_quarter", "next_quarter"];
+
+fn parse_interval(s: &str) -> Result<&'static str, String> {
+    KLINE_INTERVALS
+        .iter()
+        .find(|i| **i == s)
+        .copied()
+        .ok_or_else(|| format!("unsupported kline interval '{s}'"))
+}
+
+fn parse_contract(s: Option<&str>) -> Result<&'static str, String> {
+    let s = s.unwrap_or("perpetual");
+    CONTRACT_TYPES
+        .iter()
+        .find(|c| **c == s)
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "unsupported contract type '{s}' (expected {})",
+                CONTRACT_TYPES.join(", ")
+            )
+        })
+}
+
+impl StreamKind {
+    /// Spot has no mark price, liquidation or continuous contract streams. Spot depth
+    /// updates every 100ms or 1000ms (its default), futures depth every 100ms, 250ms (the
+    /// default) or 500ms.
+    pub fn supported_on(&self, market: Market) -> bool {
+        match (market, self) {
+            (
+                Market::Spot,
+                Self::MarkPrice { .. } | Self::ForceOrder | Self::ContinuousKline { .. },
+            ) => false,
+            (Market::Spot, Self::PartialDepth { speed_ms, .. } | Self::DiffDepth { speed_ms }) => {
+                matches!(*speed_ms, DEFAULT_SPEED | 100 | 1000)
+            }
+            (
+                Market::UsdM | Market::CoinM,
+                Self::PartialDepth { speed_ms, .. } | Self::DiffDepth { speed_ms },
+            ) => matches!(*speed_ms, DEFAULT_SPEED | 100 | 250 | 500),
+            (Market::UsdM | Market::CoinM, Self::Kline { interval: "1s" }) => false,
+            _ => true,
+        }
+    }
+
+    /// Full stream name for `symbol`. Continuous klines are keyed by pair and contract
+    /// type rather than by symbol.
+    pub fn stream_name(&self, symbol: &str) -> String {
+        match self {
+            Self::ContinuousKline { contract, interval } => {
+                format!("{symbol}_{contract}@continuousKline_{interval}")
+            }
+            kind => format!("{symbol}@{kind}"),
+        }
+    }
+}
+
+fn parse_speed(s: Option<&str>) -> Result<u16, String> {
+    match s {
+        None => Ok(DEFAULT_SPEED),
+        Some("100ms") => Ok(100),
+        Some("250ms") => Ok(250),
+        Some("500ms") => Ok(500),
+        Some("1000ms") => Ok(1000),
+        Some(other) => Err(format!(
+            "unsupported update speed '{other}' (expected 100ms, 250ms, 500ms or 1000ms)"
+        )),
+    }
+}
+
+impl FromStr for StreamKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, modifier) = match s.split_once('@') {
+            Some((name, modifier)) => (name, Some(modifier)),
+            None => (s, None),
+        };
+
+        match (name, modifier) {
+            ("aggTrade", None) => Ok(Self::AggTrade),
+            ("bookTicker", None) => Ok(Self::BookTicker),
+            ("forceOrder", None) => Ok(Self::ForceOrder),
+            ("markPrice", None) => Ok(Self::MarkPrice {
+                every_second: false,
+            }),
+            ("markPrice", Some("1s")) => Ok(Self::MarkPrice { every_second: true }),
+            (kline, None) if kline.starts_with("kline_") => Ok(Self::Kline {
+                interval: parse_interval(&kline["kline_".len()..])?,
+            }),
+            // e.g. continuousKline_1m or continuousKline_1h@current_quarter
+            (kline, contract) if kline.starts_with("continuousKline_") => {
+                Ok(Self::ContinuousKline {
+                    contract: parse_contract(contract)?,
+                    interval: parse_interval(&kline["continuousKline_".len()..])?,
+                })
+            }
+            ("depth", speed) => Ok(Self::DiffDepth {
+                speed_ms: parse_speed(speed)?,
+            }),
+            (depth, speed) if depth.starts_with("depth") => {
+                let levels: u8 = depth["depth".len()..]
+                    .parse()
+                    .map_err(|_| format!("invalid depth stream '{s}'"))?;
+                if ![5, 10, 20].contains(&levels) {
+                    return Err(format!(
+                        "partial depth must be 5, 10 or 20 levels, got {levels}"
+                    ));
+                }
+                Ok(Self::PartialDepth {
+                    levels,
+                    speed_ms: parse_speed(speed)?,
+                })
+            }
+            _ => Err(format!("unknown stream kind '{s}'")),
+        }
+    }
+}
+
+impl fmt::Display for StreamKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AggTrade => write!(f, "aggTrade"),
+            Self::BookTicker => write!(f, "bookTicker"),
+            Self::ForceOrder => write!(f, "forceOrder"),
+            Self::MarkPrice { every_second: true } => write!(f, "markPrice@1s"),
+            Self::MarkPrice {
+                every_second: false,
+            } => write!(f, "markPrice"),
+            // the default speed has no suffix: 250ms only exists on futures and 1000ms only
+            // on spot, and each is its market's default
+            Self::PartialDepth {
+                levels,
+                speed_ms: DEFAULT_SPEED | 250 | 1000,
+            } => write!(f, "depth{levels}"),
+            Self::PartialDepth { levels, speed_ms } => write!(f, "depth{levels}@{speed_ms}ms"),
+            Self::DiffDepth {
+                speed_ms: DEFAULT_SPEED | 250 | 1000,
+            } => write!(f, "depth"),
+            Self::DiffDepth { speed_ms } => write!(f, "depth@{speed_ms}ms"),
+            Self::Kline { interval } => write!(f, "kline_{interval}"),
+            Self::ContinuousKline {
+                contract: "perpetual",
+                interval,
+            } => write!(f, "continuousKline_{interval}"),
+            Self::ContinuousKline { contract, interval } => {
+                write!(f, "continuousKline_{interval}@{contract}")
+            }
+        }
+    }
+}
+
+/// A `--sym` entry, optionally qualified with the market it's subscribed on, e.g.
+/// `coinm:btcusd_perp`. Unqualified symbols are subscribed on every market.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketSymbol {
+    pub market: Option<Market>,
+    pub symbol: String,
+}
+
+impl MarketSymbol {
+    fn applies_to(&self, market: Market) -> bool {
+        self.market.is_none_or(|m| m == market)
+    }
+}
+
+impl FromStr for MarketSymbol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (market, symbol) = match s.split_once(':') {
+            Some((market, symbol)) => (Some(market.trim().parse()?), symbol),
+            None => (None, s),
+        };
+
+        let symbol = symbol.trim().to_lowercase();
+        if symbol.is_empty() {
+            return Err(format!("missing symbol in '{s}'"));
+        }
+
+        Ok(Self { market, symbol })
+    }
+}
+
+/// Per-symbol override in the form `btcusdt=aggTrade,depth5@100ms`, the symbol may be
+/// market-qualified like `--sym`
+#[derive(Debug, Clone)]
+pub struct SymbolStreams {
+    pub symbol: MarketSymbol,
+    pub kinds: Vec<StreamKind>,
+}
+
+impl FromStr for SymbolStreams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (symbol, kinds) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected <symbol>=<kind>[,<kind>...], got '{s}'"))?;
+
+        let kinds = kinds
+            .split(',')
+            .map(|k| k.trim().parse())
+            .collect::<Result<Vec<StreamKind>, _>>()?;
+
+        Ok(Self {
+            symbol: symbol.parse()?,
+            kinds,
+        })
+    }
+}
+
+/// Resolve the full list of `<symbol>@<kind>` stream names requested on the command line
+/// for `market`. Only symbols that are unqualified or qualified with `market` are used.
+/// Symbols with an override use only their own kinds, everything else uses `--streams`.
+/// Kinds the market doesn't offer are skipped.
+pub fn stream_names(cli: &Cli, market: Market) -> Vec<String> {
+    let overrides: Vec<&SymbolStreams> = cli
+        .sym_streams
+        .iter()
+        .filter(|o| o.symbol.applies_to(market))
+        .collect();
+
+    let mut symbols: Vec<&str> = cli
+        .sym
+        .iter()
+        .filter(|s| s.applies_to(market))
+        .map(|s| s.symbol.as_str())
+        .collect();
+
+    for o in &overrides {
+        if !symbols.contains(&o.symbol.symbol.as_str()) {
+            symbols.push(&o.symbol.symbol);
+        }
+    }
+
+    let mut names = Vec::new();
+
+    for sym in symbols {
+        // a market-qualified override wins over an unqualified one
+        let kinds = overrides
+            .iter()
+            .filter(|o| o.symbol.symbol == sym)
+            .max_by_key(|o| o.symbol.market.is_some())
+            .map(|o| o.kinds.as_slice())
+            .unwrap_or(&cli.streams);
+
+        for kind in kinds {
+            if !kind.supported_on(market) {
+                warn!(
+                    "{} is not available on {}, skipping it for {}",
+                    kind, market, sym
+                );
+                continue;
+            }
+
+            let name = kind.stream_name(sym);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(s: &str) -> StreamKind {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn depth_speeds_follow_each_market() {
+        for s in ["depth", "depth20", "depth@100ms", "depth@1000ms"] {
+            assert!(kind(s).supported_on(Market::Spot), "{s} on spot");
+        }
+        for s in ["depth@250ms", "depth20@500ms"] {
+            assert!(!kind(s).supported_on(Market::Spot), "{s} on spot");
+        }
+
+        for s in ["depth", "depth5@100ms", "depth@250ms", "depth@500ms"] {
+            assert!(kind(s).supported_on(Market::UsdM), "{s} on usdm");
+        }
+        assert!(!kind("depth@1000ms").supported_on(Market::UsdM));
+    }
+
+    #[test]
+    fn default_speeds_render_without_suffix() {
+        assert_eq!(kind("depth").to_string(), "depth");
+        assert_eq!(kind("depth@1000ms").to_string(), "depth");
+        assert_eq!(kind("depth10@250ms").to_string(), "depth10");
+        assert_eq!(kind("depth20@100ms").to_string(), "depth20@100ms");
+    }
+
+    #[test]
+    fn qualified_symbols_only_subscribe_on_their_market() {
+        use clap::Parser;
+
+        let cli = Cli::parse_from([
+            "prog",
+            "--market=spot,coinm",
+            "--streams=aggTrade",
+            "--sym",
+            "ethusdt",
+            "spot:BTCUSDT",
+            "coinm:btcusd_perp",
+            "--sym-streams=coinm:ethusd_perp=bookTicker",
+        ]);
+
+        assert_eq!(
+            stream_names(&cli, Market::Spot),
+            ["ethusdt@aggTrade", "btcusdt@aggTrade"]
+        );
+        assert_eq!(
+            stream_names(&cli, Market::CoinM),
+            [
+                "ethusdt@aggTrade",
+                "btcusd_perp@aggTrade",
+                "ethusd_perp@bookTicker"
+            ]
+        );
+        assert!("usdx:btcusdt".parse::<MarketSymbol>().is_err());
+    }
+}
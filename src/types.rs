@@ -1,33 +1,171 @@
-use clap::Parser;
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use rust_decimal::Decimal;
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-fn string_to_f64<'de, D>(deserializer: D) -> Result<f64, D::Error>
+use crate::bars::BarSpec;
+use crate::config::DbArgs;
+use crate::data_manip::OrderSide;
+use crate::market::{Market, RestOverride};
+use crate::streams::{MarketSymbol, StreamKind, SymbolStreams};
+
+// binance sends prices and quantities as strings, parse them exactly rather than via f64.
+// the visitor parses from the input slice directly so no intermediate String is built
+struct DecimalVisitor;
+
+impl<'de> Visitor<'de> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a decimal string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Decimal, E> {
+        Decimal::from_str(v).map_err(E::custom)
+    }
+}
+
+fn string_to_decimal<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let s: String = Deserialize::deserialize(deserializer)?;
-    s.parse::<f64>().map_err(serde::de::Error::custom)
+    deserializer.deserialize_str(DecimalVisitor)
 }
 
-fn vec_of_string_pairs_to_f64<'de, D>(deserializer: D) -> Result<Vec<[f64; 2]>, D::Error>
+// COIN-M sends "" for fields that don't apply to the contract (funding rate on delivery)
+fn opt_string_to_decimal<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let v: Vec<[String; 2]> = Deserialize::deserialize(deserializer)?;
-    v.into_iter()
-        .map(|[s1, s2]| {
-            let f1 = s1.parse::<f64>().map_err(serde::de::Error::custom)?;
-            let f2 = s2.parse::<f64>().map_err(serde::de::Error::custom)?;
-            Ok([f1, f2])
-        })
-        .collect()
+    struct OptDecimalVisitor;
+
+    impl<'de> Visitor<'de> for OptDecimalVisitor {
+        type Value = Option<Decimal>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a decimal string or an empty string")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Option<Decimal>, E> {
+            if v.is_empty() {
+                return Ok(None);
+            }
+            Decimal::from_str(v).map(Some).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_str(OptDecimalVisitor)
+}
+
+/// One `["price", "qty"]` level
+struct Level([Decimal; 2]);
+
+impl<'de> Deserialize<'de> for Level {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LevelVisitor;
+
+        impl<'de> Visitor<'de> for LevelVisitor {
+            type Value = Level;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a [price, quantity] pair")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Level, A::Error> {
+                let price = seq
+                    .next_element_seed(DecimalSeed)?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let qty = seq
+                    .next_element_seed(DecimalSeed)?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+                if seq.next_element::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(3, &self));
+                }
+
+                Ok(Level([price, qty]))
+            }
+        }
+
+        deserializer.deserialize_seq(LevelVisitor)
+    }
+}
+
+struct DecimalSeed;
+
+impl<'de> DeserializeSeed<'de> for DecimalSeed {
+    type Value = Decimal;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Decimal, D::Error> {
+        deserializer.deserialize_str(DecimalVisitor)
+    }
+}
+
+/// Deserializes a list of levels by appending to an existing buffer, so a caller that
+/// keeps its buffers between messages does not allocate once they have grown to size.
+pub struct LevelsSeed<'a>(pub &'a mut Vec<[Decimal; 2]>);
+
+impl<'de> DeserializeSeed<'de> for LevelsSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LevelsSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of [price, quantity] pairs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        self.0.clear();
+        if let Some(n) = seq.size_hint() {
+            self.0.reserve(n);
+        }
+
+        while let Some(Level(level)) = seq.next_element()? {
+            self.0.push(level);
+        }
+
+        Ok(())
+    }
+}
+
+fn vec_of_string_pairs_to_decimal<'de, D>(deserializer: D) -> Result<Vec<[Decimal; 2]>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    // binance sends at most 20 levels on partial depth streams
+    let mut levels = Vec::with_capacity(20);
+    LevelsSeed(&mut levels).deserialize(deserializer)?;
+    Ok(levels)
 }
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 pub struct Cli {
+    // subscribed on every market, or only on one when qualified, e.g. coinm:btcusd_perp
     #[arg(short, long, default_value = "btcusdt", num_args=1..)]
-    pub sym: Vec<String>,
+    pub sym: Vec<MarketSymbol>,
+
+    // one connection per market: spot, usdm and/or coinm
+    #[arg(long, default_value = "usdm", num_args=1.., value_delimiter = ',')]
+    pub market: Vec<Market>,
+
+    // stream kinds subscribed for every symbol without an override
+    #[arg(long, default_value = "depth20@100ms", value_delimiter = ',')]
+    pub streams: Vec<StreamKind>,
+
+    // e.g. --sym-streams btcusdt=aggTrade,bookTicker,depth5@100ms
+    #[arg(long)]
+    pub sym_streams: Vec<SymbolStreams>,
 
     #[arg(short, long, default_value = "utc", value_parser = ["utc", "local"])]
     pub tz: String,
@@ -35,113 +173,589 @@ pub struct Cli {
     #[arg(long, action = clap::ArgAction::SetTrue)]
     pub del_db: bool,
 
+    // TOML file with a [database] section, see config.rs
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub db: DbArgs,
+
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
+
+    // ordered workers; messages for one stream always land on the same shard
+    #[arg(long, default_value_t = 8)]
+    pub shards: usize,
+
+    // batch trades and depth in memory and load them with binary COPY
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub copy_ingest: bool,
+
+    #[arg(long, default_value_t = 5_000)]
+    pub batch_size: usize,
+
+    #[arg(long, default_value_t = 500)]
+    pub flush_ms: u64,
+
+    #[arg(long, default_value_t = 500)]
+    pub reconnect_initial_ms: u64,
+
+    #[arg(long, default_value_t = 60)]
+    pub reconnect_max_secs: u64,
+
+    #[arg(long, default_value_t = 300)]
+    pub idle_timeout_secs: u64,
+
+    // binance drops combined streams at 24h, rotate comfortably before that (0 disables)
+    #[arg(long, default_value_t = 84_600)]
+    pub rotate_after_secs: u64,
+
+    #[arg(long, default_value_t = 10)]
+    pub rotation_overlap_secs: u64,
+
+    // maintain a full local book from diff depth (`depth`, `depth@100ms`) streams
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub full_book: bool,
+
+    // REST base per market, e.g. --rest-url spot=http://localhost:8080 (repeatable),
+    // markets without one use their own endpoint
+    #[arg(long)]
+    pub rest_url: Vec<RestOverride>,
+
+    #[arg(long, default_value_t = 1000)]
+    pub snapshot_limit: u32,
+
+    // also upsert the in-progress kline on every update, not just closed bars
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub store_open_klines: bool,
+
+    // locally built bars from aggTrade, e.g. --bars time:250ms,time:1m,volume:10,tick:500
+    #[arg(long, value_delimiter = ',')]
+    pub bars: Vec<BarSpec>,
+
+    // close a quiet symbol's time bar this long after its bucket ends on the local clock
+    #[arg(long, default_value_t = 2_000)]
+    pub bar_close_grace_ms: i64,
+
+    // imbalance, microprice, depth bands, slope and level stats per depth update
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub book_metrics: bool,
+
+    // N for order book imbalance over the top N levels
+    #[arg(long, value_delimiter = ',', default_value = "1,5,10")]
+    pub imbalance_levels: Vec<usize>,
+
+    // X for quantity resting within X bps of mid
+    #[arg(long, value_delimiter = ',', default_value = "5,10,25")]
+    pub depth_bands_bps: Vec<f64>,
+
+    // sample spot vs USD-M perp basis per symbol, needs --market spot,usdm
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pub basis: bool,
+
+    // basis sampling clock
+    #[arg(long, default_value_t = 1000)]
+    pub basis_interval_ms: u64,
+
+    // a leg older than this at sample time is stale and the symbol is skipped
+    #[arg(long, default_value_t = 5000)]
+    pub basis_max_age_ms: i64,
+
+    // unix socket accepting SUBSCRIBE/UNSUBSCRIBE/LIST_SUBSCRIPTIONS commands,
+    // prefixed with the market (SUBSCRIBE spot ...) when several are subscribed
+    #[arg(long)]
+    pub control_socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
-pub struct DepthUpdate {
-    pub stream: String,
-    pub data: DepthUpdateData,
+impl Cli {
+    /// REST base for `market`, the `--rest-url` override if one was given
+    pub fn rest_url(&self, market: Market) -> &str {
+        self.rest_url
+            .iter()
+            .find(|o| o.market == market)
+            .map_or(market.rest_url(), |o| o.url.as_str())
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Market impact of a hypothetical order over stored depth, printed as CSV
+    Impact(ImpactArgs),
+}
+
+#[derive(Args, Debug)]
+#[command(group = clap::ArgGroup::new("size").required(true))]
+pub struct ImpactArgs {
+    #[arg(long)]
+    pub symbol: String,
+
+    #[arg(long, default_value = "usdm")]
+    pub market: Market,
+
+    // buy walks the asks, sell walks the bids
+    #[arg(long)]
+    pub side: OrderSide,
+
+    // base asset quantity on every market (COIN-M contracts are converted), e.g. --qty 50
+    // for a 50 BTC order
+    #[arg(long, group = "size")]
+    pub qty: Option<f64>,
+
+    // quote notional instead of a quantity, USD on COIN-M
+    #[arg(long, group = "size")]
+    pub notional: Option<f64>,
+
+    // RFC 3339, e.g. 2025-01-01T00:00:00Z
+    #[arg(long)]
+    pub from: DateTime<Utc>,
+
+    // defaults to now
+    #[arg(long)]
+    pub to: Option<DateTime<Utc>>,
+
+    // also replay depth rows stored before partial and diff depth were told apart, only
+    // safe if no diff depth streams were recorded for the symbol back then
+    #[arg(long)]
+    pub include_legacy: bool,
+}
+
+#[derive(Deserialize, Clone, Default)]
 #[allow(dead_code)]
 pub struct DepthUpdateData {
-    pub e: String,
     #[serde(rename = "E")]
     pub e2: i64,
+    // spot diff depth carries neither transaction time nor `pu`
     #[serde(rename = "T")]
-    pub t: i64,
+    pub t: Option<i64>,
     pub s: String,
     #[serde(rename = "U")]
     pub u: i64,
     #[serde(rename = "u")]
     pub u2: i64,
     #[serde(rename = "pu")]
-    pub p: i64,
-    #[serde(deserialize_with = "vec_of_string_pairs_to_f64")]
-    pub b: Vec<[f64; 2]>,
-    #[serde(deserialize_with = "vec_of_string_pairs_to_f64")]
-    pub a: Vec<[f64; 2]>,
+    pub p: Option<i64>,
+    #[serde(deserialize_with = "vec_of_string_pairs_to_decimal")]
+    pub b: Vec<[Decimal; 2]>,
+    #[serde(deserialize_with = "vec_of_string_pairs_to_decimal")]
+    pub a: Vec<[Decimal; 2]>,
+}
+
+impl DepthUpdateData {
+    /// Decode a bare `depthUpdate` payload into `self`, reusing the symbol and level
+    /// buffers of the previous update. Once warmed up this performs no allocation.
+    pub fn decode_into(&mut self, raw: &str) -> serde_json::Result<()> {
+        let mut de = serde_json::Deserializer::from_str(raw);
+        DepthUpdateSeed(self).deserialize(&mut de)?;
+        de.end()
+    }
+}
+
+struct DepthUpdateSeed<'a>(&'a mut DepthUpdateData);
+
+impl<'de> DeserializeSeed<'de> for DepthUpdateSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DepthUpdateSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a depthUpdate object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let out = self.0;
+
+        // nothing from the previous update may leak through a field this one lacks
+        out.e2 = 0;
+        out.t = None;
+        out.s.clear();
+        out.u = 0;
+        out.u2 = 0;
+        out.p = None;
+        out.b.clear();
+        out.a.clear();
+
+        // borrowed keys are fine here, binance field names never contain escapes
+        while let Some(key) = map.next_key::<&str>()? {
+            match key {
+                "E" => out.e2 = map.next_value()?,
+                "T" => out.t = Some(map.next_value()?),
+                "s" => out.s.push_str(map.next_value()?),
+                "U" => out.u = map.next_value()?,
+                "u" => out.u2 = map.next_value()?,
+                "pu" => out.p = Some(map.next_value()?),
+                "b" => map.next_value_seed(LevelsSeed(&mut out.b))?,
+                "a" => map.next_value_seed(LevelsSeed(&mut out.a))?,
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+
+        if out.s.is_empty() {
+            return Err(de::Error::missing_field("s"));
+        }
+
+        Ok(())
+    }
 }
 
+/// REST depth response used to seed the local order book. Spot omits the timestamps.
 #[derive(Deserialize)]
 #[allow(dead_code)]
-pub struct BookTickerUpdate {
-    pub stream: String,
-    pub data: BookTickerData,
+pub struct DepthSnapshot {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: i64,
+    #[serde(rename = "E")]
+    pub e2: Option<i64>,
+    #[serde(rename = "T")]
+    pub t: Option<i64>,
+    #[serde(deserialize_with = "vec_of_string_pairs_to_decimal")]
+    pub bids: Vec<[Decimal; 2]>,
+    #[serde(deserialize_with = "vec_of_string_pairs_to_decimal")]
+    pub asks: Vec<[Decimal; 2]>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[allow(dead_code)]
 pub struct BookTickerData {
-    pub e: String,
     pub u: i64,
     #[serde(rename = "E")]
     pub e2: i64,
     #[serde(rename = "T")]
-    pub t: i64,
+    pub t: Option<i64>,
     pub s: String,
-    #[serde(deserialize_with = "string_to_f64")]
-    pub b: f64,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub b: Decimal,
     #[serde(rename = "B")]
-    #[serde(deserialize_with = "string_to_f64")]
-    pub bq: f64,
-    #[serde(deserialize_with = "string_to_f64")]
-    pub a: f64,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub bq: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub a: Decimal,
     #[serde(rename = "A")]
-    #[serde(deserialize_with = "string_to_f64")]
-    pub aq: f64,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub aq: Decimal,
 }
 
-#[derive(Deserialize)]
-#[allow(dead_code)]
-pub struct MarkPriceUpdate {
-    pub stream: String,
-    pub data: MarkPriceUpdateData,
-}
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[allow(dead_code)]
 pub struct MarkPriceUpdateData {
-    pub e: String,
     #[serde(rename = "E")]
     pub e2: i64,
     pub s: String,
-    #[serde(deserialize_with = "string_to_f64")]
-    pub p: f64,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub p: Decimal,
     #[serde(rename = "P")]
-    #[serde(deserialize_with = "string_to_f64")]
-    pub p2: f64,
-    #[serde(deserialize_with = "string_to_f64")]
-    pub i: f64,
-    #[serde(deserialize_with = "string_to_f64")]
-    pub r: f64,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub p2: Decimal,
+    // absent on COIN-M
+    #[serde(default, deserialize_with = "opt_string_to_decimal")]
+    pub i: Option<Decimal>,
+    // "" on delivery contracts
+    #[serde(deserialize_with = "opt_string_to_decimal")]
+    pub r: Option<Decimal>,
     #[serde(rename = "T")]
     pub t: i64,
 }
-#[derive(Deserialize)]
-#[allow(dead_code)]
-pub struct AggTrade {
-    pub stream: String,
-    pub data: AggTradeData,
-}
-#[derive(Deserialize)]
+
+#[derive(Deserialize, Clone)]
 #[allow(dead_code)]
 pub struct AggTradeData {
-    pub e: String,
     #[serde(rename = "E")]
     pub e2: i64,
     pub a: i64,
     pub s: String,
-    #[serde(deserialize_with = "string_to_f64")]
-    pub p: f64,
-    #[serde(deserialize_with = "string_to_f64")]
-    pub q: f64,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub p: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub q: Decimal,
     pub f: i64,
     pub l: i64,
     #[serde(rename = "T")]
     pub t: i64,
     pub m: bool,
 }
+
+/// Liquidation order, nested under `o` in a `forceOrder` event
+#[derive(Deserialize, Clone)]
+#[allow(dead_code)]
+pub struct LiquidationOrder {
+    pub s: String,
+    #[serde(rename = "S")]
+    pub side: String,
+    // order type
+    pub o: String,
+    // time in force
+    pub f: String,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub q: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub p: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub ap: Decimal,
+    #[serde(rename = "X")]
+    pub status: String,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub l: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub z: Decimal,
+    #[serde(rename = "T")]
+    pub t: i64,
+}
+
+#[derive(Deserialize, Clone)]
+#[allow(dead_code)]
+pub struct ForceOrderData {
+    #[serde(rename = "E")]
+    pub e2: i64,
+    pub o: LiquidationOrder,
+}
+
+/// Candlestick under `k`, shared by `kline` and `continuous_kline` events
+#[derive(Deserialize, Clone)]
+#[allow(dead_code)]
+pub struct KlineBar {
+    // open and close time
+    pub t: i64,
+    #[serde(rename = "T")]
+    pub t2: i64,
+    pub i: String,
+    // first and last trade id (update id on continuous klines)
+    pub f: i64,
+    #[serde(rename = "L")]
+    pub l2: i64,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub o: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub c: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub h: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub l: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub v: Decimal,
+    pub n: i64,
+    // bar closed
+    pub x: bool,
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub q: Decimal,
+    #[serde(rename = "V")]
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub v2: Decimal,
+    #[serde(rename = "Q")]
+    #[serde(deserialize_with = "string_to_decimal")]
+    pub q2: Decimal,
+}
+
+#[derive(Deserialize, Clone)]
+#[allow(dead_code)]
+pub struct KlineData {
+    #[serde(rename = "E")]
+    pub e2: i64,
+    pub s: String,
+    pub k: KlineBar,
+}
+
+#[derive(Deserialize, Clone)]
+#[allow(dead_code)]
+pub struct ContinuousKlineData {
+    #[serde(rename = "E")]
+    pub e2: i64,
+    pub ps: String,
+    pub ct: String,
+    pub k: KlineBar,
+}
+
+/// Every payload we handle. `decode_message` picks the variant from the stream name
+pub enum MarketEvent {
+    AggTrade(AggTradeData),
+    DepthUpdate(DepthUpdateData),
+    BookTicker(BookTickerData),
+    MarkPriceUpdate(MarkPriceUpdateData),
+    ForceOrder(ForceOrderData),
+    Kline(KlineData),
+    ContinuousKline(ContinuousKlineData),
+}
+
+impl MarketEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AggTrade(_) => "aggTrade",
+            Self::DepthUpdate(_) => "depthUpdate",
+            Self::BookTicker(_) => "bookTicker",
+            Self::MarkPriceUpdate(_) => "markPriceUpdate",
+            Self::ForceOrder(_) => "forceOrder",
+            Self::Kline(_) => "kline",
+            Self::ContinuousKline(_) => "continuous_kline",
+        }
+    }
+}
+
+/// Spot partial depth: a bare snapshot without `e`, the symbol only appears in the stream name
+#[derive(Deserialize)]
+struct SpotPartialDepth {
+    #[serde(rename = "lastUpdateId")]
+    last_update_id: i64,
+    #[serde(deserialize_with = "vec_of_string_pairs_to_decimal")]
+    bids: Vec<[Decimal; 2]>,
+    #[serde(deserialize_with = "vec_of_string_pairs_to_decimal")]
+    asks: Vec<[Decimal; 2]>,
+}
+
+/// Spot bookTicker: no `e`, no event or transaction time
+#[derive(Deserialize)]
+struct SpotBookTicker {
+    u: i64,
+    s: String,
+    #[serde(deserialize_with = "string_to_decimal")]
+    b: Decimal,
+    #[serde(rename = "B")]
+    #[serde(deserialize_with = "string_to_decimal")]
+    bq: Decimal,
+    #[serde(deserialize_with = "string_to_decimal")]
+    a: Decimal,
+    #[serde(rename = "A")]
+    #[serde(deserialize_with = "string_to_decimal")]
+    aq: Decimal,
+}
+
+#[derive(Deserialize)]
+struct Envelope<T> {
+    stream: String,
+    data: T,
+}
+
+/// Combined-stream frame: `{"stream": "<symbol>@<kind>", "data": {...}}`
+pub struct StreamEnvelope {
+    pub stream: String,
+    pub data: MarketEvent,
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Json(serde_json::Error),
+    UnsupportedEvent { stream: String },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "malformed message: {e}"),
+            Self::UnsupportedEvent { stream } => write!(f, "unsupported event type on {stream}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            Self::UnsupportedEvent { .. } => None,
+        }
+    }
+}
+
+/// Combined-stream frames start with `{"stream":"<symbol>@<kind>"`, so the stream name
+/// can be sliced out without parsing the payload.
+pub fn stream_name(raw: &str) -> &str {
+    raw.strip_prefix("{\"stream\":\"")
+        .and_then(|rest| rest.split_once('"'))
+        .map(|(name, _)| name)
+        .unwrap_or("")
+}
+
+/// Spot sends partial depth and bookTicker without an `e` tag, so they are recognised by
+/// stream name and mapped onto the common types, stamped with the local receive time.
+fn decode_spot_untagged(raw: &str) -> Option<Result<StreamEnvelope, DecodeError>> {
+    let kind = stream_name(raw).split('@').nth(1)?;
+    let received = chrono::Utc::now().timestamp_millis();
+
+    let decoded = if kind == "bookTicker" {
+        serde_json::from_str::<Envelope<SpotBookTicker>>(raw).map(|env| StreamEnvelope {
+            stream: env.stream,
+            data: MarketEvent::BookTicker(BookTickerData {
+                u: env.data.u,
+                e2: received,
+                t: None,
+                s: env.data.s,
+                b: env.data.b,
+                bq: env.data.bq,
+                a: env.data.a,
+                aq: env.data.aq,
+            }),
+        })
+    } else if kind.len() > "depth".len()
+        && kind.starts_with("depth")
+        && kind[5..].bytes().all(|b| b.is_ascii_digit())
+    {
+        serde_json::from_str::<Envelope<SpotPartialDepth>>(raw).map(|env| {
+            let s = env.stream.split('@').next().unwrap_or("").to_uppercase();
+            StreamEnvelope {
+                stream: env.stream,
+                data: MarketEvent::DepthUpdate(DepthUpdateData {
+                    e2: received,
+                    t: None,
+                    s,
+                    u: env.data.last_update_id,
+                    u2: env.data.last_update_id,
+                    p: None,
+                    b: env.data.bids,
+                    a: env.data.asks,
+                }),
+            }
+        })
+    } else {
+        return None;
+    };
+
+    Some(decoded.map_err(DecodeError::Json))
+}
+
+fn decode_as<T: de::DeserializeOwned>(
+    raw: &str,
+    wrap: fn(T) -> MarketEvent,
+) -> Result<StreamEnvelope, DecodeError> {
+    let env: Envelope<T> = serde_json::from_str(raw).map_err(DecodeError::Json)?;
+    Ok(StreamEnvelope {
+        stream: env.stream,
+        data: wrap(env.data),
+    })
+}
+
+/// Decode a combined-stream frame into its typed event in a single pass.
+///
+/// The event type is known from the stream name, so the payload is deserialized straight
+/// into the concrete struct. Dispatching on `e` with an internally tagged enum would make
+/// serde buffer the whole payload first, allocating for every depth level.
+pub fn decode_message(market: Market, raw: &str) -> Result<StreamEnvelope, DecodeError> {
+    if market == Market::Spot
+        && let Some(decoded) = decode_spot_untagged(raw)
+    {
+        return decoded;
+    }
+
+    let stream = stream_name(raw);
+    let kind = stream.split_once('@').map_or("", |(_, kind)| kind);
+
+    match kind {
+        "aggTrade" => decode_as(raw, MarketEvent::AggTrade),
+        // diff and partial depth share the depthUpdate payload on futures
+        k if k.starts_with("depth") => decode_as(raw, MarketEvent::DepthUpdate),
+        "bookTicker" => decode_as(raw, MarketEvent::BookTicker),
+        k if k.starts_with("markPrice") => decode_as(raw, MarketEvent::MarkPriceUpdate),
+        "forceOrder" => decode_as(raw, MarketEvent::ForceOrder),
+        k if k.starts_with("kline_") => decode_as(raw, MarketEvent::Kline),
+        k if k.starts_with("continuousKline_") => decode_as(raw, MarketEvent::ContinuousKline),
+        _ => Err(DecodeError::UnsupportedEvent {
+            stream: stream.to_string(),
+        }),
+    }
+}